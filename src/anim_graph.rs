@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::Path;
+
+use crate::animation::{AnimationSM, Playback};
+use crate::assets::AssetError;
+use crate::atlas::Atlas;
+
+/*
+An animation graph describes an AnimationSM by name, so every tank (or anything else) with the
+same behaviour can share one definition. Graph files are plain text, one statement per line:
+
+    # comments and blank lines are ignored
+    start <state>
+    state <name> <clip> [once] [reverse | pingpong] [finish <event>]
+    on <from> <input> <to>
+    then <from> <to>
+
+Clips are atlas clip names (see atlas.rs). A state plays its clip on a loop unless marked once, in
+which case it holds the clip's last frame until it moves on; reverse and pingpong change the order
+frames are played in. "on" switches state when AnimationSM::input receives <input>; "then" switches
+state when a "once" clip finishes, and "finish" names the event AnimationSM reports when that
+happens. <from> may be * to mean every state; when several transitions match, the first one listed
+wins. States can be used before they are declared, but every state used must be declared somewhere.
+*/
+pub struct AnimGraph {
+    states: Vec<GraphState>,
+    // (src, dest, input) with the same meaning as in AnimationSM; input "" means "when finished"
+    transitions: Vec<(usize, usize, String)>,
+    start: usize,
+}
+
+struct GraphState {
+    name: String,
+    clip: String,
+    loops: bool,
+    playback: Playback,
+    finish_event: Option<String>,
+}
+
+impl AnimGraph {
+    pub fn with_file(path: &Path) -> Result<Self, AssetError> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| AssetError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&src, &path.display().to_string())
+    }
+
+    /// Parses graph text; origin names the source in error messages
+    pub fn parse(src: &str, origin: &str) -> Result<Self, AssetError> {
+        let err = |line: usize, msg: String| AssetError::Parse {
+            origin: origin.to_string(),
+            line,
+            msg,
+        };
+
+        let mut states: Vec<GraphState> = vec![];
+        // Transitions and start refer to states by name until every state has been read
+        let mut edges: Vec<(usize, String, String, String)> = vec![];
+        let mut start: Option<(usize, String)> = None;
+
+        for (line_no, line) in src.lines().enumerate() {
+            let line_no = line_no + 1;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                [first, ..] if first.starts_with('#') => {}
+                ["start", name] => {
+                    if start.is_some() {
+                        return Err(err(line_no, "start given twice".to_string()));
+                    }
+                    start = Some((line_no, name.to_string()));
+                }
+                ["state", name, clip, rest @ ..] => {
+                    let (mut loops, mut playback, mut finish_event) =
+                        (true, Playback::Forward, None);
+                    let mut rest = rest;
+                    while !rest.is_empty() {
+                        rest = match rest {
+                            ["once", tail @ ..] => {
+                                loops = false;
+                                tail
+                            }
+                            ["reverse", tail @ ..] => {
+                                playback = Playback::Reverse;
+                                tail
+                            }
+                            ["pingpong", tail @ ..] => {
+                                playback = Playback::PingPong;
+                                tail
+                            }
+                            ["finish", event, tail @ ..] if !loops => {
+                                finish_event = Some(event.to_string());
+                                tail
+                            }
+                            _ => return Err(err(line_no, format!("unexpected {:?}", rest[0]))),
+                        };
+                    }
+                    if states.iter().any(|s| s.name == *name) {
+                        return Err(err(line_no, format!("state {:?} declared twice", name)));
+                    }
+                    states.push(GraphState {
+                        name: name.to_string(),
+                        clip: clip.to_string(),
+                        loops,
+                        playback,
+                        finish_event,
+                    });
+                }
+                ["on", from, input, to] => {
+                    edges.push((line_no, from.to_string(), to.to_string(), input.to_string()));
+                }
+                ["then", from, to] => {
+                    edges.push((line_no, from.to_string(), to.to_string(), String::new()));
+                }
+                _ => return Err(err(line_no, format!("can't understand {:?}", line.trim()))),
+            }
+        }
+
+        let find = |line: usize, name: &str| {
+            states
+                .iter()
+                .position(|s| s.name == name)
+                .ok_or_else(|| err(line, format!("no state named {:?}", name)))
+        };
+
+        let mut transitions = vec![];
+        for (line, from, to, input) in edges.iter() {
+            let to = find(*line, to)?;
+            if from == "*" {
+                transitions.extend((0..states.len()).map(|src| (src, to, input.clone())));
+            } else {
+                transitions.push((find(*line, from)?, to, input.clone()));
+            }
+        }
+        let start = match start {
+            Some((line, name)) => find(line, &name)?,
+            None => return Err(err(0, "no start state".to_string())),
+        };
+
+        Ok(Self {
+            states,
+            transitions,
+            start,
+        })
+    }
+
+    /// Builds a state machine out of atlas clips
+    pub fn build(&self, atlas: &Atlas, now: usize) -> Result<AnimationSM, AssetError> {
+        let animations = self
+            .states
+            .iter()
+            .map(|s| {
+                atlas.animation(&s.clip, now, s.loops).map(|a| {
+                    a.with_playback(s.playback)
+                        .with_finish_event(s.finish_event.clone())
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AnimationSM::new(
+            animations,
+            self.transitions.clone(),
+            self.start,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error_line(src: &str) -> Option<usize> {
+        match AnimGraph::parse(src, "test") {
+            Err(AssetError::Parse { line, .. }) => Some(line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_graph() {
+        let graph = AnimGraph::parse(
+            "on idle go run\nstart idle\nstate idle a\nstate run b once\nthen run idle",
+            "test",
+        );
+        let graph = graph.ok().unwrap();
+        assert_eq!(graph.states.len(), 2);
+        assert_eq!(
+            graph.transitions,
+            vec![(0, 1, "go".to_string()), (1, 0, String::new())]
+        );
+        assert_eq!(graph.start, 0);
+    }
+
+    #[test]
+    fn rejects_undeclared_state() {
+        assert_eq!(
+            parse_error_line("start idle\nstate idle a\non idle go run"),
+            Some(3)
+        );
+        assert_eq!(parse_error_line("start run\nstate idle a"), Some(1));
+    }
+
+    #[test]
+    fn rejects_duplicate_state() {
+        assert_eq!(
+            parse_error_line("start idle\nstate idle a\nstate idle b"),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keyword() {
+        assert_eq!(
+            parse_error_line("start idle\nstate idle a\njump idle"),
+            Some(3)
+        );
+        assert_eq!(parse_error_line("start idle\nstate idle a twice"), Some(2));
+    }
+
+    #[test]
+    fn rejects_finish_without_once() {
+        assert_eq!(
+            parse_error_line("start fire\nstate fire a finish fired"),
+            Some(2)
+        );
+        assert!(AnimGraph::parse("start fire\nstate fire a once finish fired", "test").is_ok());
+    }
+}
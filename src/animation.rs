@@ -1,4 +1,13 @@
-use crate::types::Rect;
+use crate::types::{Rect, Vec2i};
+
+/// Order in which an animation shows its frames
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Playback {
+    Forward,
+    Reverse,
+    // Forward then back again, without showing the end frames twice
+    PingPong,
+}
 
 pub struct Animation {
     /**
@@ -6,9 +15,22 @@ pub struct Animation {
      * frame_rects represent areas of a sprite sheet (handled by Sprite) to draw per tick
      */
     frame_rects: Vec<Rect>,
+    frame_pivots: Vec<Vec2i>,
+    // Whether each frame is drawn mirrored (left-to-right, top-to-bottom)
+    frame_flips: Vec<(bool, bool)>,
+    // Named events reported when a frame is reached, e.g. "muzzle_flash"
+    frame_events: Vec<Option<String>>,
+    // Named event reported when a non-looping animation finishes, e.g. "death_complete"
+    finish_event: Option<String>,
     frame_times: Vec<usize>,
+    playback: Playback,
+    // Ticks of animation time per tick of game time
+    speed: f64,
+    // Animation time had already reached base when game time was start_time
     start_time: usize,
-    total_time: usize,
+    base: f64,
+    // Game time at which the animation was paused
+    paused_at: Option<usize>,
     loops: bool,
 }
 
@@ -20,39 +42,153 @@ impl Animation {
         loops: bool,
     ) -> Self {
         assert!(frame_rects.len() == frame_times.len());
+        assert!(!frame_rects.is_empty());
 
         Animation {
+            frame_pivots: vec![Vec2i(0, 0); frame_rects.len()],
+            frame_flips: vec![(false, false); frame_rects.len()],
+            frame_events: vec![None; frame_rects.len()],
+            finish_event: None,
             frame_rects,
-            frame_times: frame_times.clone(),
+            frame_times,
+            playback: Playback::Forward,
+            speed: 1.0,
             start_time,
-            total_time: frame_times.iter().sum(),
+            base: 0.0,
+            paused_at: None,
             loops,
         }
     }
 
+    /// Sets the point of each frame that lines up with the sprite's position
+    pub fn with_pivots(mut self, frame_pivots: Vec<Vec2i>) -> Self {
+        assert!(frame_pivots.len() == self.frame_rects.len());
+        self.frame_pivots = frame_pivots;
+        self
+    }
+
+    /// Sets which frames are drawn mirrored
+    pub fn with_flips(mut self, frame_flips: Vec<(bool, bool)>) -> Self {
+        assert!(frame_flips.len() == self.frame_rects.len());
+        self.frame_flips = frame_flips;
+        self
+    }
+
+    /// Sets the event reported when each frame is reached
+    pub fn with_events(mut self, frame_events: Vec<Option<String>>) -> Self {
+        assert!(frame_events.len() == self.frame_rects.len());
+        self.frame_events = frame_events;
+        self
+    }
+
+    /// Sets the event reported when this (non-looping) animation finishes
+    pub fn with_finish_event(mut self, event: Option<String>) -> Self {
+        self.finish_event = event;
+        self
+    }
+
+    pub fn with_playback(mut self, playback: Playback) -> Self {
+        self.playback = playback;
+        self
+    }
+
     pub fn current_frame(&self, now: usize) -> Rect {
-        // Calculate current frame to display using the current frame number
-        let mut frame_index: usize = 0;
-        let mut tot = 0;
-        let rem = if self.loops {
-            (now - self.start_time) % self.total_time
+        self.frame_rects[self.frame_index(now)]
+    }
+
+    pub fn current_pivot(&self, now: usize) -> Vec2i {
+        self.frame_pivots[self.frame_index(now)]
+    }
+
+    pub fn current_flip(&self, now: usize) -> (bool, bool) {
+        self.frame_flips[self.frame_index(now)]
+    }
+
+    /// Plays the animation again from its first frame, keeping its speed and whether it's paused
+    pub fn restart(&mut self, now: usize) {
+        self.start_time = now;
+        self.base = 0.0;
+        if self.paused_at.is_some() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    pub fn pause(&mut self, now: usize) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    pub fn resume(&mut self, now: usize) {
+        if let Some(at) = self.paused_at.take() {
+            self.base = self.anim_time(at);
+            self.start_time = now;
+        }
+    }
+
+    /// Changes playback speed from now on; 0.5 is half speed, 2.0 double
+    pub fn set_speed(&mut self, speed: f64, now: usize) {
+        assert!(speed >= 0.0);
+        let now = self.paused_at.unwrap_or(now);
+        self.base = self.anim_time(now);
+        self.start_time = now;
+        if let Some(at) = self.paused_at.as_mut() {
+            *at = now;
+        }
+        self.speed = speed;
+    }
+
+    // How far into the animation we are at game time now, in ticks
+    fn anim_time(&self, now: usize) -> f64 {
+        let now = self.paused_at.map_or(now, |at| at.min(now));
+        // Times before the animation started count as its start
+        self.base + now.saturating_sub(self.start_time) as f64 * self.speed
+    }
+
+    // Ticks to play every frame once, in playback order
+    fn cycle_time(&self) -> usize {
+        let total: usize = self.frame_times.iter().sum();
+        let n = self.frame_times.len();
+        match self.playback {
+            Playback::PingPong if n > 2 => total + self.frame_times[1..n - 1].iter().sum::<usize>(),
+            _ => total,
+        }
+    }
+
+    fn frame_index(&self, now: usize) -> usize {
+        let cycle = self.cycle_time();
+        if cycle == 0 {
+            return 0;
+        }
+        let elapsed = self.anim_time(now) as usize;
+        // Looping animations wrap around; the others hold their last frame
+        let mut rem = if self.loops {
+            elapsed % cycle
         } else {
-            now - self.start_time
+            elapsed.min(cycle - 1)
         };
 
-        for (i, ft) in self.frame_times.iter().enumerate() {
-            if rem <= tot {
-                frame_index = i;
+        // Walk through the frames in playback order until rem runs out
+        let n = self.frame_rects.len();
+        let order: Box<dyn Iterator<Item = usize>> = match self.playback {
+            Playback::Forward => Box::new(0..n),
+            Playback::Reverse => Box::new((0..n).rev()),
+            Playback::PingPong => Box::new((0..n).chain((1..n.saturating_sub(1)).rev())),
+        };
+        let mut frame_index = 0;
+        for i in order {
+            frame_index = i;
+            if rem < self.frame_times[i] {
                 break;
             }
-            tot += ft;
+            rem -= self.frame_times[i];
         }
 
-        self.frame_rects[frame_index]
+        frame_index
     }
 
     pub fn done(&self, now: usize) -> bool {
-        !self.loops && (now - self.start_time >= self.total_time)
+        !self.loops && self.anim_time(now) >= self.cycle_time() as f64
     }
 }
 
@@ -67,6 +203,10 @@ pub struct AnimationSM {
     // update_time: usize,
     start_index: usize,
     current_anim: usize,
+    // (animation, frame) seen by the last update, to notice when a new frame is reached
+    last_frame: Option<(usize, usize)>,
+    // Events reported since the game last drained them
+    events: Vec<String>,
 }
 
 impl AnimationSM {
@@ -82,6 +222,8 @@ impl AnimationSM {
             // update_time,
             start_index,
             current_anim: start_index,
+            last_frame: None,
+            events: vec![],
         }
     }
 
@@ -95,7 +237,8 @@ impl AnimationSM {
         for (src, dest, read) in self.transitions.iter() {
             if *src == self.current_anim && *read == input {
                 self.current_anim = *dest;
-                self.animations[self.current_anim].start_time = now;
+                self.animations[self.current_anim].restart(now);
+                self.last_frame = None;
                 break;
             }
         }
@@ -103,15 +246,45 @@ impl AnimationSM {
         // Do nothing if transition is not found
     }
 
-    fn update_anim(&mut self, now: usize) {
+    #[allow(dead_code)]
+    pub fn pause(&mut self, now: usize) {
+        for anim in self.animations.iter_mut() {
+            anim.pause(now);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn resume(&mut self, now: usize) {
+        for anim in self.animations.iter_mut() {
+            anim.resume(now);
+        }
+    }
+
+    /// Sets the playback speed of every state, so it carries over transitions
+    pub fn set_speed(&mut self, speed: f64, now: usize) {
+        for anim in self.animations.iter_mut() {
+            anim.set_speed(speed, now);
+        }
+    }
+
+    /// Takes the events reported by the animations since the last call
+    pub fn drain_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn update_anim(&mut self, now: usize) {
         // Update if animation is finished
         // Moves according to first matching transition found, given no input
         // Ignores later ones if there are multiple
         if self.animations[self.current_anim].done(now) {
+            if let Some(event) = &self.animations[self.current_anim].finish_event {
+                self.events.push(event.clone());
+            }
+
             let mut transition_found = false;
 
             for (src, dest, read) in self.transitions.iter() {
-                if *src == self.current_anim && *read == "" {
+                if *src == self.current_anim && read.is_empty() {
                     self.current_anim = *dest;
                     transition_found = true;
                     break;
@@ -122,6 +295,17 @@ impl AnimationSM {
             if !transition_found {
                 self.current_anim = self.start_index;
             }
+            self.animations[self.current_anim].restart(now);
+        }
+
+        // Report the event of a frame the first time it comes up
+        let anim = &self.animations[self.current_anim];
+        let frame = anim.frame_index(now);
+        if self.last_frame != Some((self.current_anim, frame)) {
+            self.last_frame = Some((self.current_anim, frame));
+            if let Some(event) = &anim.frame_events[frame] {
+                self.events.push(event.clone());
+            }
         }
     }
 }
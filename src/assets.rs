@@ -1,23 +1,52 @@
-use rand::Rng;
+use std::fmt;
 use std::rc::Rc;
 
 use crate::animation::*;
+use crate::atlas::{Atlas, SpriteSheet};
 use crate::collision::*;
 use crate::entity::*;
-use crate::screen::*;
+use crate::level::*;
+use crate::particles::ParticleStyle;
 use crate::sprite::*;
-use crate::texture::*;
+use crate::theme::Theme;
 use crate::types::*;
 
-const TILE_SZ: usize = 16;
+/// Errors from loading sprite sheets and looking up pieces of them
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetError {
+    UnknownTheme(String),
+    InvalidTileId(i32),
+    MissingFrame(String),
+    MissingGraph(String),
+    FrameOutOfBounds(String),
+    Io(String),
+    Parse {
+        origin: String,
+        line: usize,
+        msg: String,
+    },
+}
 
-pub fn level_walls(
-    tile_sheet: &Rc<Texture>,
-    frame_count: usize,
-    screen_dims: Vec2i,
-) -> Vec<Entity<Terrain>> {
-    let mut walls: Vec<Entity<Terrain>> = vec![];
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::UnknownTheme(name) => write!(f, "no theme named {:?}", name),
+            AssetError::InvalidTileId(id) => write!(f, "no tile with ID {}", id),
+            AssetError::MissingFrame(name) => write!(f, "no frame named {:?}", name),
+            AssetError::MissingGraph(name) => write!(f, "no animation graph named {:?}", name),
+            AssetError::FrameOutOfBounds(name) => {
+                write!(f, "frame {:?} lies outside its texture", name)
+            }
+            AssetError::Io(msg) => write!(f, "couldn't read file: {}", msg),
+            AssetError::Parse { origin, line, msg } => write!(f, "{}:{}: {}", origin, line, msg),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
 
+/// The original hand-made map, sized to fill the screen
+pub fn classic_grid(screen_dims: Vec2i) -> LevelGrid {
     // 0 = top-left corner, 1 = top-right corner, 2 = bottom-right corner, 3 = bottom-left corner
     // 4 = top edge, 5 = right edge, 6 = bottom edge, 7 = left edge
     // 8 = top-left inner corner, 9 = top-right inner corner, 10 = bottom-right inner corner, 11 = bottom-left inner corner
@@ -57,32 +86,51 @@ pub fn level_walls(
         9,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  8,  9,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  8,
     ];
     assert!((screen_dims.0 * screen_dims.1) as usize == tile_ids.len() * TILE_SZ * TILE_SZ);
+    let dims = (
+        screen_dims.0 as usize / TILE_SZ,
+        screen_dims.1 as usize / TILE_SZ,
+    );
+
+    // Only the border of the map is indestructible
+    let destructible = (0..tile_ids.len())
+        .map(|i| {
+            let (x, y) = (i % dims.0, i / dims.0);
+            x != 0 && y != 0 && x != dims.0 - 1 && y != dims.1 - 1
+        })
+        .collect();
+    let spawns = find_spawns(dims, &tile_ids, 4);
+
+    LevelGrid {
+        dims,
+        tile_ids,
+        destructible,
+        spawns,
+    }
+}
+
+/// Turns a level layout into terrain entities, drawn with the tiles of theme
+pub fn level_walls(
+    tile_sheet: &SpriteSheet,
+    frame_count: usize,
+    grid: &LevelGrid,
+    theme: &Theme,
+) -> Result<Vec<Entity<Terrain>>, AssetError> {
+    let mut walls: Vec<Entity<Terrain>> = vec![];
 
-    for (i, tid) in tile_ids.iter().enumerate() {
-        let tile_rect = match get_tile_rect(*tid, "snow").unwrap() {
+    for (i, tid) in grid.tile_ids.iter().enumerate() {
+        let tile_rect = match get_tile_rect(&tile_sheet.atlas, *tid, theme)? {
             Some(rect) => rect,
             None => continue,
         };
 
         let tile_pos = Vec2i(
-            (i * TILE_SZ) as i32 % screen_dims.0,
-            (i * TILE_SZ) as i32 / screen_dims.0 * TILE_SZ as i32,
+            ((i % grid.dims.0) * TILE_SZ) as i32,
+            ((i / grid.dims.0) * TILE_SZ) as i32,
         );
 
-        let mut destructible = true;
-        if i < screen_dims.0 as usize / TILE_SZ {
-            destructible = false;
-        } else if i % (screen_dims.0 as usize / TILE_SZ) == 0 {
-            destructible = false;
-        } else if i % (screen_dims.0 as usize / TILE_SZ) == screen_dims.0 as usize / TILE_SZ - 1 {
-            destructible = false;
-        } else if i / (screen_dims.0 as usize / TILE_SZ) == (screen_dims.1 as usize / TILE_SZ - 1) {
-            destructible = false;
-        }
-
         walls.push(Entity::new(
             Sprite::new(
-                tile_sheet,
+                &tile_sheet.texture,
                 AnimationSM::new(
                     vec![Animation::new(vec![tile_rect], vec![60], frame_count, true)],
                     vec![],
@@ -99,178 +147,146 @@ pub fn level_walls(
                     h: TILE_SZ as u16,
                 },
                 frame_count,
-                destructible,
+                grid.destructible[i],
                 1,
             ),
         ));
     }
 
-    walls
+    Ok(walls)
 }
 
-/// Returns Rect containing the tile corresponding to ID, with terrain type of tile_terrain
+/// Returns Rect containing the tile corresponding to ID, in the tileset panel of theme
 /// Possible values of id:
 ///     0 = top-left corner, 1 = top-right corner, 2 = bottom-right corner, 3 = bottom-left corner
 ///     4 = top edge, 5 = right edge, 6 = bottom edge, 7 = left edge
 ///     8 = top-left inner corner, 9 = top-right inner corner, 10 = bottom-right inner corner, 11 = bottom-left inner corner
 ///     12 = center
 ///     -1 = return Ok(None)
-/// Possible values of tile_terrain:
-///     "snow", "grass"
-/// Error values:
-///     0 = tile_terrain value not acceptable
-///     1 = id value not acceptable
-fn get_tile_rect(id: i32, tile_terrain: &str) -> Result<Option<Rect>, usize> {
-    let mut terrain_offset: Vec2i;
-
-    match tile_terrain {
-        "snow" => terrain_offset = Vec2i(0, 0),
-        "grass" => terrain_offset = Vec2i(-544, -360),
-        _ => return Err(0),
-    };
-
-    let mut tile_coords: Vec2i;
-
-    match id {
-        0 => tile_coords = Vec2i(640, 544),
-        1 => tile_coords = Vec2i(688, 544),
-        2 => tile_coords = Vec2i(800, 544),
-        3 => tile_coords = Vec2i(768, 544),
-        4 => tile_coords = Vec2i(576, 384),
-        5 => tile_coords = Vec2i(592, 400),
-        6 => tile_coords = Vec2i(784, 544),
-        7 => tile_coords = Vec2i(560, 400),
-        8 => tile_coords = Vec2i(592, 688),
-        9 => tile_coords = Vec2i(624, 688),
-        10 => tile_coords = Vec2i(704, 520),
-        11 => tile_coords = Vec2i(720, 520),
-        12 => tile_coords = Vec2i(576, 400),
+fn get_tile_rect(atlas: &Atlas, id: i32, theme: &Theme) -> Result<Option<Rect>, AssetError> {
+    let name = match id {
+        0 => "ground/top_left",
+        1 => "ground/top_right",
+        2 => "ground/bottom_right",
+        3 => "ground/bottom_left",
+        4 => "ground/top",
+        5 => "ground/right",
+        6 => "ground/bottom",
+        7 => "ground/left",
+        8 => "ground/inner_top_left",
+        9 => "ground/inner_top_right",
+        10 => "ground/inner_bottom_right",
+        11 => "ground/inner_bottom_left",
+        12 => "ground/center",
         -1 => return Ok(None),
-        _ => return Err(1),
+        _ => return Err(AssetError::InvalidTileId(id)),
     };
+    let rect = atlas.frame(name)?.rect;
 
     Ok(Some(Rect {
-        x: tile_coords.0 + terrain_offset.0,
-        y: tile_coords.1 + terrain_offset.1,
-        w: 16,
-        h: 16,
+        x: rect.x + theme.tile_offset.0,
+        y: rect.y + theme.tile_offset.1,
+        ..rect
     }))
 }
 
-pub fn player_entity(sprite_sheet: &Rc<Texture>, frame_count: usize) -> Entity<Mobile> {
-    let pos = Vec2i(100, 100);
+// Multiplied into the tank art, which is grey with white trim, to give each team its colour
+const PLAYER_TINT: Rgba = Rgba(90, 230, 90, 255);
+const ENEMY_TINT: Rgba = Rgba(255, 150, 60, 255);
 
-    let mut anims: Vec<Animation> = (0..4)
-        .map(|x| {
-            Animation::new(
-                get_sprite_rects(x, "green").unwrap(),
-                vec![60],
-                frame_count,
-                true,
-            )
-        })
-        .collect();
-    let anims2: Vec<Animation> = (4..8)
-        .map(|x| {
-            Animation::new(
-                get_sprite_rects(x, "green").unwrap(),
-                vec![2, 2],
-                frame_count,
-                true,
-            )
-        })
-        .collect();
-    anims.extend(anims2);
+pub fn player_entity(sprite_sheet: &SpriteSheet, frame_count: usize, pos: Vec2i) -> Entity<Mobile> {
+    let collider = Mobile::player(pos.0, pos.1, 10);
+    let sprite = tank_sprite(sprite_sheet, PLAYER_TINT, frame_count, pos, &collider);
 
-    #[rustfmt::skip]
-    let trans = vec![
-        (0, 1, "right".to_string()), (0, 2, "down".to_string()), (0, 3, "left".to_string()),
-        (1, 0, "up".to_string()), (1, 2, "down".to_string()), (0, 3, "left".to_string()),
-        (2, 0, "up".to_string()), (2, 1, "right".to_string()), (2, 3, "left".to_string()),
-        (3, 0, "up".to_string()), (3, 1, "right".to_string()), (3, 2, "down".to_string()),
-        (0, 4, "move".to_string()), (1, 5, "move".to_string()), (2, 6, "move".to_string()), (3, 7, "move".to_string()), 
-        (4, 0, "stop".to_string()), (5, 1, "stop".to_string()), (6, 2, "stop".to_string()), (7, 3, "stop".to_string()), 
-    ];
+    Entity::new(sprite, pos, collider)
+}
 
-    let anim_sm = AnimationSM::new(anims, trans, 0);
+pub fn enemy_entity(sprite_sheet: &SpriteSheet, frame_count: usize, pos: Vec2i) -> Entity<Mobile> {
+    let collider = Mobile::enemy(pos.0, pos.1, 1);
+    let sprite = tank_sprite(sprite_sheet, ENEMY_TINT, frame_count, pos, &collider);
 
-    let sprite = Sprite::new(&sprite_sheet, anim_sm, pos);
+    Entity::new(sprite, pos, collider)
+}
 
-    Entity::new(sprite, pos, Mobile::player(pos.0, pos.1, 10))
+/// The kinds of particle the game throws about
+pub struct EffectStyles {
+    // Shells glancing off walls
+    pub sparks: Rc<ParticleStyle>,
+    // Bits of knocked-out terrain
+    pub debris: Rc<ParticleStyle>,
+    // Rising from damaged tanks
+    pub smoke: Rc<ParticleStyle>,
+    // A small explosion where a shell strikes a tank
+    pub blast: Rc<ParticleStyle>,
 }
 
-pub fn enemy_entity(sprite_sheet: &Rc<Texture>, frame_count: usize, pos: Vec2i) -> Entity<Mobile> {
-    let mut anims: Vec<Animation> = (0..4)
-        .map(|x| {
-            Animation::new(
-                get_sprite_rects(x, "orange").unwrap(),
-                vec![60],
-                frame_count,
-                true,
-            )
-        })
-        .collect();
-    let anims2: Vec<Animation> = (4..8)
-        .map(|x| {
-            Animation::new(
-                get_sprite_rects(x, "orange").unwrap(),
-                vec![2, 2],
-                frame_count,
-                true,
-            )
-        })
+pub fn effect_styles(sprite_sheet: &SpriteSheet) -> Result<EffectStyles, AssetError> {
+    let blast_frames = sprite_sheet
+        .atlas
+        .sequence("explosion")?
+        .iter()
+        .map(|f| f.rect)
         .collect();
-    anims.extend(anims2);
-
-    #[rustfmt::skip]
-    let trans = vec![
-        (0, 1, "right".to_string()), (0, 2, "down".to_string()), (0, 3, "left".to_string()),
-        (1, 0, "up".to_string()), (1, 2, "down".to_string()), (0, 3, "left".to_string()),
-        (2, 0, "up".to_string()), (2, 1, "right".to_string()), (2, 3, "left".to_string()),
-        (3, 0, "up".to_string()), (3, 1, "right".to_string()), (3, 2, "down".to_string()),
-        (0, 4, "move".to_string()), (1, 5, "move".to_string()), (2, 6, "move".to_string()), (3, 7, "move".to_string()), 
-        (4, 0, "stop".to_string()), (5, 1, "stop".to_string()), (6, 2, "stop".to_string()), (7, 3, "stop".to_string()), 
-    ];
-
-    let anim_sm = AnimationSM::new(anims, trans, 0);
 
-    let sprite = Sprite::new(&sprite_sheet, anim_sm, pos);
-
-    Entity::new(sprite, pos, Mobile::enemy(pos.0, pos.1, 1))
+    Ok(EffectStyles {
+        sparks: Rc::new(ParticleStyle {
+            life: (8, 16),
+            speed: (1.0, 3.0),
+            gravity: Vec2f(0.0, 0.0),
+            drag: 0.15,
+            colour: (Rgba(255, 240, 160, 255), Rgba(255, 110, 20, 0)),
+            radius: (1.5, 0.5),
+            frames: None,
+        }),
+        debris: Rc::new(ParticleStyle {
+            life: (20, 40),
+            speed: (0.5, 2.5),
+            gravity: Vec2f(0.0, 0.0),
+            drag: 0.08,
+            colour: (Rgba(110, 90, 70, 255), Rgba(80, 70, 60, 0)),
+            radius: (2.5, 1.5),
+            frames: None,
+        }),
+        smoke: Rc::new(ParticleStyle {
+            life: (40, 70),
+            speed: (0.2, 0.6),
+            // Drifts up the screen
+            gravity: Vec2f(0.0, -0.01),
+            drag: 0.02,
+            colour: (Rgba(90, 90, 90, 160), Rgba(60, 60, 60, 0)),
+            radius: (3.0, 9.0),
+            frames: None,
+        }),
+        blast: Rc::new(ParticleStyle {
+            life: (35, 35),
+            speed: (0.0, 0.0),
+            gravity: Vec2f(0.0, 0.0),
+            drag: 0.0,
+            colour: (Rgba(255, 255, 255, 255), Rgba(255, 255, 255, 160)),
+            radius: (12.0, 16.0),
+            frames: Some((Rc::clone(&sprite_sheet.texture), blast_frames)),
+        }),
+    })
 }
 
-/// 0 = facing up, 1 = facing right, 2 = facing down, 3 = facing left
-/// 4 = moving up, 5 = moving right, 6 = moving down, 7 = moving left
-/// colors = "green", "orange"
-/// errors: 0 = invalid color, 1 = invalid id
-fn get_sprite_rects(id: usize, color: &str) -> Result<Vec<Rect>, usize> {
-    let offset = match color {
-        "green" => Vec2i(0, 0),
-        "orange" => Vec2i(0, 66),
-        _ => return Err(0),
-    };
-
-    let pos = match id {
-        0 => vec![Vec2i(47, 29)],
-        1 => vec![Vec2i(113, 29)],
-        2 => vec![Vec2i(80, 63)],
-        3 => vec![Vec2i(13, 62)],
-        4 => vec![Vec2i(178, 29), Vec2i(179, 29)],
-        5 => vec![Vec2i(245, 29), Vec2i(245, 30)],
-        6 => vec![Vec2i(212, 63), Vec2i(213, 63)],
-        7 => vec![Vec2i(146, 62), Vec2i(146, 63)],
-        _ => return Err(1),
-    };
-
-    Ok(pos
-        .into_iter()
-        .map(|p| Rect {
-            x: p.0 + offset.0,
-            y: p.1 + offset.1,
-            w: 24,
-            h: 24,
-        })
-        .collect())
+/// A tinted tank body with a turret and a damage flash on top, both pinned to collider's middle
+fn tank_sprite(
+    sprite_sheet: &SpriteSheet,
+    tint: Rgba,
+    frame_count: usize,
+    pos: Vec2i,
+    collider: &Mobile,
+) -> Sprite {
+    let centre = Vec2i(collider.rect.w as i32 / 2, collider.rect.h as i32 / 2);
+    let layer = |name: &str| sprite_sheet.animation_sm(name, frame_count).unwrap();
+
+    let mut sprite = Sprite::new(&sprite_sheet.texture, layer("tank"), pos)
+        .with_layer("turret", layer("turret"), centre, 1)
+        .with_layer("flash", layer("flash"), centre, 2);
+    if let Some(body) = sprite.layer_mut(BODY) {
+        body.style.tint = tint;
+    }
+    sprite
 }
 
 /*
@@ -542,51 +558,3 @@ pub fn rock_entity(sprite_sheet: &Rc<Texture>, frame_count: usize, pos: Vec2i) -
     )
 }
 */
-
-pub fn get_font_letter(c: char) -> Option<Rect> {
-    if c.is_lowercase() {
-        Some(Rect {
-            x: (c as i32 - 'a' as i32) * 18 + 9,
-            y: 5,
-            w: 18,
-            h: 18,
-        })
-    } else if c.is_uppercase() {
-        Some(Rect {
-            x: (c as i32 - 'A' as i32) * 18 + 9,
-            y: 23,
-            w: 18,
-            h: 18,
-        })
-    } else if c.is_numeric() {
-        Some(Rect {
-            x: (c as i32 - '0' as i32) * 18 + 9,
-            y: 41,
-            w: 18,
-            h: 18,
-        })
-    } else {
-        None
-    }
-}
-
-pub fn draw_string(
-    string: &str,
-    screen: &mut Screen,
-    font_sheet: &Rc<Texture>,
-    pos: Vec2i,
-    scroll: Vec2i,
-) {
-    for (i, c) in string.chars().enumerate() {
-        match get_font_letter(c) {
-            None => {}
-            Some(rect) => {
-                screen.bitblt(
-                    font_sheet,
-                    rect,
-                    Vec2i(pos.0 + 18 * i as i32, scroll.1 + pos.1),
-                );
-            }
-        }
-    }
-}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::anim_graph::AnimGraph;
+use crate::animation::{Animation, AnimationSM};
+use crate::assets::AssetError;
+use crate::texture::Texture;
+use crate::types::{Rect, Vec2i};
+
+/// How long a frame is shown if its descriptor doesn't say
+const DEFAULT_FRAME_TIME: usize = 60;
+
+/// One named region of a sprite sheet
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AtlasFrame {
+    pub rect: Rect,
+    /// Point within rect that lines up with the sprite's position; (0, 0) is the top-left corner
+    pub pivot: Vec2i,
+    /// How many ticks to show this frame for when it's part of an animation
+    pub time: usize,
+    /// Event the animation reports when it reaches this frame
+    pub event: Option<String>,
+    /// Whether to draw rect mirrored (left-to-right, top-to-bottom); pivot is in drawn coordinates
+    pub flip: (bool, bool),
+}
+
+/*
+An atlas describes where things are in a sprite sheet so that code can ask for them by name.
+Descriptor files are plain text, one frame per line:
+
+    # comments and blank lines are ignored
+    frame <name> <x> <y> <w> <h> [pivot <x> <y>] [time <ticks>] [event <name>] [flip x|y|xy]
+
+Flipped frames let one region of the sheet serve for e.g. both facing left and facing right.
+Frames named <clip>/0, <clip>/1, ... are the frames of the animation <clip>; a frame named just
+<clip> is a one-frame animation.
+*/
+pub struct Atlas {
+    frames: HashMap<String, AtlasFrame>,
+}
+
+impl Atlas {
+    pub fn with_file(path: &Path) -> Result<Self, AssetError> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| AssetError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&src, &path.display().to_string())
+    }
+
+    /// Parses descriptor text; origin names the source in error messages
+    pub fn parse(src: &str, origin: &str) -> Result<Self, AssetError> {
+        let mut frames = HashMap::new();
+
+        for (line_no, line) in src.lines().enumerate() {
+            let err = |msg: &str| AssetError::Parse {
+                origin: origin.to_string(),
+                line: line_no + 1,
+                msg: msg.to_string(),
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            if words[0] != "frame" {
+                return Err(err(&format!("unknown directive {:?}", words[0])));
+            }
+            if words.len() < 6 {
+                return Err(err("expected frame <name> <x> <y> <w> <h>"));
+            }
+
+            let num = |s: &str| {
+                s.parse::<i32>()
+                    .map_err(|_| err(&format!("bad number {:?}", s)))
+            };
+            let size = |s: &str| {
+                s.parse::<u16>()
+                    .map_err(|_| err(&format!("bad size {:?}", s)))
+            };
+            let name = words[1].to_string();
+            let rect = Rect {
+                x: num(words[2])?,
+                y: num(words[3])?,
+                w: size(words[4])?,
+                h: size(words[5])?,
+            };
+            let mut frame = AtlasFrame {
+                rect,
+                pivot: Vec2i(0, 0),
+                time: DEFAULT_FRAME_TIME,
+                event: None,
+                flip: (false, false),
+            };
+
+            let mut rest = &words[6..];
+            while !rest.is_empty() {
+                match rest {
+                    ["pivot", x, y, tail @ ..] => {
+                        frame.pivot = Vec2i(num(x)?, num(y)?);
+                        rest = tail;
+                    }
+                    ["time", t, tail @ ..] => {
+                        frame.time = num(t)?.max(1) as usize;
+                        rest = tail;
+                    }
+                    ["event", e, tail @ ..] => {
+                        frame.event = Some(e.to_string());
+                        rest = tail;
+                    }
+                    ["flip", axes, tail @ ..] => {
+                        frame.flip = match *axes {
+                            "x" => (true, false),
+                            "y" => (false, true),
+                            "xy" => (true, true),
+                            _ => return Err(err(&format!("can't flip {:?}", axes))),
+                        };
+                        rest = tail;
+                    }
+                    _ => return Err(err(&format!("unexpected {:?}", rest[0]))),
+                }
+            }
+
+            if frames.insert(name.clone(), frame).is_some() {
+                return Err(err(&format!("frame {:?} defined twice", name)));
+            }
+        }
+
+        Ok(Self { frames })
+    }
+
+    /// Makes sure every frame lies inside texture
+    pub fn validate(&self, texture: &Texture) -> Result<(), AssetError> {
+        for (name, frame) in self.frames.iter() {
+            if !texture.valid_frame(frame.rect) {
+                return Err(AssetError::FrameOutOfBounds(name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn frame(&self, name: &str) -> Result<&AtlasFrame, AssetError> {
+        self.frames
+            .get(name)
+            .ok_or_else(|| AssetError::MissingFrame(name.to_string()))
+    }
+
+    /// Names of all the frames, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.frames.keys().map(|k| k.as_str())
+    }
+
+    /// The frames of the clip called name, in order
+    pub fn sequence(&self, name: &str) -> Result<Vec<&AtlasFrame>, AssetError> {
+        if let Some(frame) = self.frames.get(name) {
+            return Ok(vec![frame]);
+        }
+
+        let prefix = format!("{}/", name);
+        let mut numbered: Vec<(usize, &AtlasFrame)> = self
+            .frames
+            .iter()
+            .filter_map(|(k, f)| {
+                k.strip_prefix(&prefix)
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .map(|n| (n, f))
+            })
+            .collect();
+        if numbered.is_empty() {
+            return Err(AssetError::MissingFrame(name.to_string()));
+        }
+        numbered.sort_by_key(|(n, _)| *n);
+
+        Ok(numbered.into_iter().map(|(_, f)| f).collect())
+    }
+
+    /// Builds an Animation out of the clip called name
+    pub fn animation(
+        &self,
+        name: &str,
+        start_time: usize,
+        loops: bool,
+    ) -> Result<Animation, AssetError> {
+        let frames = self.sequence(name)?;
+        Ok(Animation::new(
+            frames.iter().map(|f| f.rect).collect(),
+            frames.iter().map(|f| f.time).collect(),
+            start_time,
+            loops,
+        )
+        .with_pivots(frames.iter().map(|f| f.pivot).collect())
+        .with_events(frames.iter().map(|f| f.event.clone()).collect())
+        .with_flips(frames.iter().map(|f| f.flip).collect()))
+    }
+}
+
+/// A texture together with the atlas describing it and the animation graphs that use it
+pub struct SpriteSheet {
+    pub texture: Rc<Texture>,
+    pub atlas: Atlas,
+    graphs: HashMap<String, AnimGraph>,
+}
+
+impl SpriteSheet {
+    /// Loads an image along with the atlas descriptor next to it (same name, .atlas extension)
+    pub fn with_file(path: &Path) -> Result<Self, AssetError> {
+        let texture = Rc::new(Texture::with_file(path));
+        let atlas = Atlas::with_file(&path.with_extension("atlas"))?;
+        atlas.validate(&texture)?;
+
+        Ok(Self {
+            texture,
+            atlas,
+            graphs: HashMap::new(),
+        })
+    }
+
+    /// Loads an animation graph for this sheet, checking that it builds
+    pub fn with_graph(mut self, name: &str, path: &Path) -> Result<Self, AssetError> {
+        let graph = AnimGraph::with_file(path)?;
+        graph.build(&self.atlas, 0)?;
+        self.graphs.insert(name.to_string(), graph);
+
+        Ok(self)
+    }
+
+    /// A fresh state machine from the graph called name
+    pub fn animation_sm(&self, name: &str, now: usize) -> Result<AnimationSM, AssetError> {
+        self.graphs
+            .get(name)
+            .ok_or_else(|| AssetError::MissingGraph(name.to_string()))?
+            .build(&self.atlas, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error_line(src: &str) -> Option<usize> {
+        match Atlas::parse(src, "test") {
+            Err(AssetError::Parse { line, .. }) => Some(line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_frames() {
+        let atlas =
+            Atlas::parse("# tank\nframe tank 1 2 16 16 pivot 8 8 time 5\n", "test").unwrap();
+        let frame = atlas.frame("tank").unwrap();
+        assert_eq!(
+            frame.rect,
+            Rect {
+                x: 1,
+                y: 2,
+                w: 16,
+                h: 16
+            }
+        );
+        assert_eq!(frame.pivot, Vec2i(8, 8));
+        assert_eq!(frame.time, 5);
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        assert_eq!(
+            parse_error_line("frame a 0 0 16 16\nframe b 0 0 -16 16"),
+            Some(2)
+        );
+        assert_eq!(parse_error_line("frame a 0 0 16 -1"), Some(1));
+        assert_eq!(parse_error_line("frame a 0 0 wide 16"), Some(1));
+        assert_eq!(parse_error_line("frame a 0 0 16 1.5"), Some(1));
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::assets::AssetError;
+
+// Sample rate the mixer runs at when there's no device to ask for one
+const NULL_RATE: u32 = 44100;
+// Most sound effects playing at once; effects started past this are dropped
+const MAX_VOICES: usize = 32;
+
+/// A decoded sound, held as interleaved stereo samples at the mixer's sample rate
+pub struct Sound {
+    samples: Vec<f32>,
+}
+
+impl Sound {
+    /// Decodes a .wav or .ogg file, converting it to stereo at rate samples per second
+    pub fn with_file(path: &Path, rate: u32) -> Result<Self, AssetError> {
+        let file =
+            File::open(path).map_err(|e| AssetError::Io(format!("{}: {}", path.display(), e)))?;
+        let reader = BufReader::new(file);
+        let parse_error = |msg: String| AssetError::Parse {
+            origin: path.display().to_string(),
+            line: 0,
+            msg,
+        };
+
+        let (samples, channels, from_rate) = match path.extension().and_then(OsStr::to_str) {
+            Some("wav") => decode_wav(reader).map_err(|e| parse_error(e.to_string()))?,
+            Some("ogg") => decode_ogg(reader).map_err(|e| parse_error(e.to_string()))?,
+            _ => return Err(parse_error("not a .wav or .ogg file".to_string())),
+        };
+        if channels == 0 || from_rate == 0 {
+            return Err(parse_error("no channels or no sample rate".to_string()));
+        }
+        Ok(Self {
+            samples: to_stereo(&samples, channels, from_rate, rate),
+        })
+    }
+
+    fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+}
+
+// Interleaved samples scaled to -1..1, channel count and sample rate
+fn decode_wav(reader: BufReader<File>) -> Result<(Vec<f32>, usize, u32), hound::Error> {
+    let mut wav = hound::WavReader::new(reader)?;
+    let spec = wav.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => wav.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            wav.samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok((samples, spec.channels as usize, spec.sample_rate))
+}
+
+fn decode_ogg(reader: BufReader<File>) -> Result<(Vec<f32>, usize, u32), lewton::VorbisError> {
+    let mut ogg = lewton::inside_ogg::OggStreamReader::new(reader)?;
+    let mut samples = vec![];
+    while let Some(packet) = ogg.read_dec_packet_itl()? {
+        samples.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+    }
+    Ok((
+        samples,
+        ogg.ident_hdr.audio_channels as usize,
+        ogg.ident_hdr.audio_sample_rate,
+    ))
+}
+
+// Resamples interleaved audio to to_rate and to two channels: mono is copied to both sides, and
+// anything past the first two channels is dropped
+fn to_stereo(samples: &[f32], channels: usize, from_rate: u32, to_rate: u32) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    let channel = |frame: usize, c: usize| samples[frame * channels + c.min(channels - 1)];
+    let out_frames = (frames as u64 * to_rate as u64 / from_rate as u64) as usize;
+    let step = from_rate as f64 / to_rate as f64;
+
+    let mut out = Vec::with_capacity(out_frames * 2);
+    for i in 0..out_frames {
+        // Blend the two input frames either side of where this output frame falls
+        let at = i as f64 * step;
+        let before = (at as usize).min(frames - 1);
+        let after = (before + 1).min(frames - 1);
+        let t = (at - before as f64) as f32;
+        for c in 0..2 {
+            out.push(channel(before, c) * (1.0 - t) + channel(after, c) * t);
+        }
+    }
+    out
+}
+
+struct Voice {
+    sound: Arc<Sound>,
+    // Next frame of the sound to play
+    frame: usize,
+    // Gain for the left and right channels
+    gains: (f32, f32),
+    looping: bool,
+}
+
+impl Voice {
+    fn new(sound: &Arc<Sound>, volume: f32, pan: f32, looping: bool) -> Self {
+        // Equal-power panning, so sounds don't get quieter in the middle
+        let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        Self {
+            sound: Arc::clone(sound),
+            frame: 0,
+            gains: (volume * angle.cos(), volume * angle.sin()),
+            looping,
+        }
+    }
+
+    // Adds this voice into out (interleaved stereo); returns whether it has more to play
+    fn mix_into(&mut self, out: &mut [f32]) -> bool {
+        let samples = &self.sound.samples;
+        for frame in out.chunks_exact_mut(2) {
+            if self.frame >= self.sound.frames() {
+                if !self.looping || self.sound.frames() == 0 {
+                    return false;
+                }
+                self.frame = 0;
+            }
+            frame[0] += samples[self.frame * 2] * self.gains.0;
+            frame[1] += samples[self.frame * 2 + 1] * self.gains.1;
+            self.frame += 1;
+        }
+        true
+    }
+}
+
+/*
+The mixer adds up everything that's playing into one stream of interleaved stereo samples: any
+number of one-shot effects (up to MAX_VOICES), each with its own volume and pan, plus one music
+track that loops until it's replaced. Whatever is on the other end of the stream (a sound card, or
+nothing at all) pulls samples from it with mix().
+*/
+pub struct Mixer {
+    rate: u32,
+    voices: Vec<Voice>,
+    music: Option<Voice>,
+}
+
+impl Mixer {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            voices: Vec::with_capacity(MAX_VOICES),
+            music: None,
+        }
+    }
+
+    /// Starts a sound playing once; pan runs from -1 (hard left) to 1 (hard right)
+    pub fn play(&mut self, sound: &Arc<Sound>, volume: f32, pan: f32) {
+        if self.voices.len() < MAX_VOICES {
+            self.voices.push(Voice::new(sound, volume, pan, false));
+        }
+    }
+
+    /// Loops a sound in the middle, in place of whatever music was playing
+    pub fn play_music(&mut self, sound: &Arc<Sound>, volume: f32) {
+        self.music = Some(Voice::new(sound, volume, 0.0, true));
+    }
+
+    /// Fills out (interleaved stereo) with the next stretch of sound, clearing out effects that
+    /// have finished
+    pub fn mix(&mut self, out: &mut [f32]) {
+        for s in out.iter_mut() {
+            *s = 0.0;
+        }
+        if let Some(music) = self.music.as_mut() {
+            music.mix_into(out);
+        }
+        self.voices.retain_mut(|v| v.mix_into(out));
+        for s in out.iter_mut() {
+            *s = s.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+// Where the mixed sound goes
+enum Output {
+    // Nowhere: the game mixes sound as time passes and throws it away, so everything plays and
+    // finishes just as it would through a sound card. Used when there is no sound card (e.g.
+    // headless runs and CI) or the game was built without the sound feature.
+    Null {
+        // Frames of sound that are due but not yet mixed
+        owed: f64,
+        scratch: Vec<f32>,
+    },
+    // A sound card, which pulls samples from the mixer on its own thread as it needs them; the
+    // stream is only held on to because dropping it stops the sound
+    #[cfg(feature = "sound")]
+    Device(#[allow(dead_code)] cpal::Stream),
+}
+
+/// The game's sound: a mixer, the output it plays through and every sound loaded so far, by name
+pub struct Audio {
+    mixer: Arc<Mutex<Mixer>>,
+    output: Output,
+    sounds: HashMap<String, Arc<Sound>>,
+}
+
+impl Audio {
+    /// Plays through the default sound card if there is one, or the null output if not
+    pub fn new() -> Self {
+        #[cfg(feature = "sound")]
+        if let Some((mixer, stream)) = device::open() {
+            return Self {
+                mixer,
+                output: Output::Device(stream),
+                sounds: HashMap::new(),
+            };
+        }
+        Self::null()
+    }
+
+    /// Mixes sound without playing it anywhere
+    pub fn null() -> Self {
+        Self {
+            mixer: Arc::new(Mutex::new(Mixer::new(NULL_RATE))),
+            output: Output::Null {
+                owed: 0.0,
+                scratch: vec![],
+            },
+            sounds: HashMap::new(),
+        }
+    }
+
+    /// Loads every .wav and .ogg file in dir, named by its file name without the extension
+    pub fn load_dir(&mut self, dir: &Path) -> Result<(), AssetError> {
+        let io_error = |e: std::io::Error| AssetError::Io(format!("{}: {}", dir.display(), e));
+        let rate = self.mixer.lock().unwrap().rate;
+        for entry in std::fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            let is_sound = matches!(
+                path.extension().and_then(OsStr::to_str),
+                Some("wav") | Some("ogg")
+            );
+            if let (true, Some(name)) = (is_sound, path.file_stem().and_then(OsStr::to_str)) {
+                let sound = Sound::with_file(&path, rate)?;
+                self.sounds.insert(name.to_string(), Arc::new(sound));
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays the sound called name once (see Mixer::play); names that weren't loaded play nothing
+    pub fn play(&self, name: &str, volume: f32, pan: f32) {
+        if let Some(sound) = self.sounds.get(name) {
+            self.mixer.lock().unwrap().play(sound, volume, pan);
+        }
+    }
+
+    /// Loops the sound called name as music (see Mixer::play_music)
+    pub fn play_music(&self, name: &str, volume: f32) {
+        if let Some(sound) = self.sounds.get(name) {
+            self.mixer.lock().unwrap().play_music(sound, volume);
+        }
+    }
+
+    /// Lets dt seconds of sound play out; only the null output needs this, since a sound card
+    /// keeps its own time
+    pub fn update(&mut self, dt: f64) {
+        match &mut self.output {
+            Output::Null { owed, scratch } => {
+                let mut mixer = self.mixer.lock().unwrap();
+                *owed += dt * mixer.rate as f64;
+                let frames = *owed as usize;
+                *owed -= frames as f64;
+                scratch.resize(frames * 2, 0.0);
+                mixer.mix(scratch);
+            }
+            #[cfg(feature = "sound")]
+            Output::Device(_) => {}
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sound")]
+mod device {
+    use std::sync::{Arc, Mutex};
+
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    use super::Mixer;
+
+    // Opens a stream on the default output device that plays whatever a new mixer produces;
+    // None if there's no device or it won't play
+    pub(super) fn open() -> Option<(Arc<Mutex<Mixer>>, cpal::Stream)> {
+        let device = cpal::default_host().default_output_device()?;
+        let config = device.default_output_config().ok()?;
+        let format = config.sample_format();
+        let config: cpal::StreamConfig = config.into();
+        let mixer = Arc::new(Mutex::new(Mixer::new(config.sample_rate.0)));
+
+        let stream = match format {
+            cpal::SampleFormat::F32 => build::<f32>(&device, &config, &mixer),
+            cpal::SampleFormat::I16 => build::<i16>(&device, &config, &mixer),
+            cpal::SampleFormat::U16 => build::<u16>(&device, &config, &mixer),
+        }?;
+        stream.play().ok()?;
+        Some((mixer, stream))
+    }
+
+    fn build<T: cpal::Sample>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        mixer: &Arc<Mutex<Mixer>>,
+    ) -> Option<cpal::Stream> {
+        let mixer = Arc::clone(mixer);
+        let channels = config.channels as usize;
+        let mut stereo: Vec<f32> = vec![];
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _| {
+                    let frames = data.len() / channels;
+                    stereo.resize(frames * 2, 0.0);
+                    mixer.lock().unwrap().mix(&mut stereo);
+                    // Mono devices get both sides mixed down; any channels past the first two
+                    // are left silent
+                    for (out, lr) in data.chunks_exact_mut(channels).zip(stereo.chunks_exact(2)) {
+                        for (c, s) in out.iter_mut().enumerate() {
+                            let v = match (channels, c) {
+                                (1, _) => (lr[0] + lr[1]) / 2.0,
+                                (_, 0) | (_, 1) => lr[c],
+                                _ => 0.0,
+                            };
+                            *s = T::from(&v);
+                        }
+                    }
+                },
+                |e| eprintln!("sound output failed: {}", e),
+            )
+            .ok()
+    }
+}
@@ -0,0 +1,141 @@
+use crate::types::{Rect, Vec2f, Vec2i};
+
+// Fraction of the way to its target the camera moves each frame
+const FOLLOW_RATE: f64 = 0.1;
+// Furthest the view is knocked off target at full trauma, in screen pixels
+const MAX_SHAKE: f64 = 12.0;
+// Trauma lost per frame
+const SHAKE_DECAY: f64 = 0.02;
+
+/*
+The camera decides which part of the world is on screen. It eases towards whatever it's following,
+never shows past the edges of the level (unless the level is smaller than the view, in which case
+the level is centred), and can be shaken and zoomed out.
+
+Shake works on "trauma" between 0 and 1: explosions add trauma, it wears off over time, and the
+view is knocked about by trauma squared, so small bumps stay small and big ones feel violent.
+*/
+pub struct Camera {
+    // Middle of the view in world coordinates, before shaking
+    centre: Vec2f,
+    // Size of the screen in pixels
+    screen: (usize, usize),
+    // Part of the world the view stays within
+    bounds: Rect,
+    // Screen pixels per world pixel; less than 1 shows more of the world
+    zoom: f64,
+    trauma: f64,
+    // Frames since the camera was made, to drive the shake
+    age: usize,
+}
+
+impl Camera {
+    pub fn new(screen: (usize, usize), bounds: Rect) -> Self {
+        Self {
+            centre: Vec2f(
+                bounds.x as f64 + bounds.w as f64 / 2.0,
+                bounds.y as f64 + bounds.h as f64 / 2.0,
+            ),
+            screen,
+            bounds,
+            zoom: 1.0,
+            trauma: 0.0,
+            age: 0,
+        }
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    pub fn set_zoom(&mut self, zoom: f64) {
+        assert!(zoom > 0.0);
+        self.zoom = zoom;
+        self.centre = self.clamp(self.centre);
+    }
+
+    /// Zoom at which the whole level fits on screen; never zooms in past 1
+    pub fn fit_zoom(&self) -> f64 {
+        (self.screen.0 as f64 / self.bounds.w as f64)
+            .min(self.screen.1 as f64 / self.bounds.h as f64)
+            .min(1.0)
+    }
+
+    /// Centres the view on target straight away
+    pub fn jump_to(&mut self, target: Vec2f) {
+        self.centre = self.clamp(target);
+    }
+
+    /// Moves the view part of the way towards centring on target; call once per frame
+    pub fn follow(&mut self, target: Vec2f) {
+        let goal = self.clamp(target);
+        self.centre = Vec2f(
+            self.centre.0 + (goal.0 - self.centre.0) * FOLLOW_RATE,
+            self.centre.1 + (goal.1 - self.centre.1) * FOLLOW_RATE,
+        );
+        self.trauma = (self.trauma - SHAKE_DECAY).max(0.0);
+        self.age += 1;
+    }
+
+    /// Adds trauma (up to 1 in total) to shake the view
+    pub fn shake(&mut self, trauma: f64) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+
+    /// The part of the world in view, shake included
+    pub fn view(&self) -> Rect {
+        let (w, h) = self.view_size();
+        // A couple of out-of-step waves make a cheap, smooth wobble
+        let t = self.age as f64;
+        let strength = MAX_SHAKE * self.trauma * self.trauma / self.zoom;
+        let shake = Vec2f(
+            strength * ((t * 0.9).sin() + (t * 2.3).sin()) / 2.0,
+            strength * ((t * 1.1).cos() + (t * 2.9).sin()) / 2.0,
+        );
+        let top_left = Vec2i(
+            (self.centre.0 + shake.0 - w as f64 / 2.0).round() as i32,
+            (self.centre.1 + shake.1 - h as f64 / 2.0).round() as i32,
+        );
+
+        Rect {
+            x: top_left.0,
+            y: top_left.1,
+            w: w as u16,
+            h: h as u16,
+        }
+    }
+
+    /// Where a point on screen (in screen pixels) is in the world
+    pub fn to_world(&self, screen_pos: Vec2f) -> Vec2f {
+        let view = self.view();
+        Vec2f(
+            view.x as f64 + screen_pos.0 / self.zoom,
+            view.y as f64 + screen_pos.1 / self.zoom,
+        )
+    }
+
+    // World pixels covered by the screen at the current zoom
+    fn view_size(&self) -> (usize, usize) {
+        (
+            (self.screen.0 as f64 / self.zoom).ceil() as usize,
+            (self.screen.1 as f64 / self.zoom).ceil() as usize,
+        )
+    }
+
+    // Nearest view centre to target that keeps the view inside the level
+    fn clamp(&self, target: Vec2f) -> Vec2f {
+        let (w, h) = self.view_size();
+        let axis = |t: f64, lo: i32, len: u16, view: usize| {
+            let (lo, len, half) = (lo as f64, len as f64, view as f64 / 2.0);
+            if view as f64 >= len {
+                lo + len / 2.0
+            } else {
+                t.clamp(lo + half, lo + len - half)
+            }
+        };
+        Vec2f(
+            axis(target.0, self.bounds.x, self.bounds.w, w),
+            axis(target.1, self.bounds.y, self.bounds.h, h),
+        )
+    }
+}
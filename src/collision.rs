@@ -1,7 +1,9 @@
+use std::f64::consts::PI;
 use std::usize;
 
 use crate::entity::Entity;
 use crate::types::*;
+use crate::weapon::{self, Delivery, Weapon};
 
 // seconds per frame
 const DEPTH: usize = 4;
@@ -9,7 +11,33 @@ const WIDTH: usize = 512;
 const HEIGHT: usize = 480;
 const PITCH: usize = WIDTH * DEPTH;
 
-const PROJ_MAX_BOUNCES: i32 = 6;
+// The player's tank shrugs off half of the damage from any blast, its own or an enemy's
+const PLAYER_ARMOR: Armor = Armor {
+    plating: 0,
+    kinetic: 0.0,
+    explosive: 0.5,
+    energy: 0.0,
+};
+
+// Pixels the player's tank can drive each turn; enemy tanks drive as far as they like
+const PLAYER_FUEL: f64 = 240.0;
+
+// How the player's tank drives
+const PLAYER_HANDLING: Handling = Handling {
+    accel: 0.6,
+    max_speed: 3.0,
+    friction: 0.15,
+};
+// Tanks coasting slower than this stop dead
+const STOP_SPEED: f32 = 0.05;
+
+// Enemy tanks' turrets only turn a quarter turn either side of the way they're heading
+const ENEMY_TRAVERSE: Option<f64> = Some(std::f64::consts::FRAC_PI_2);
+
+// Shots leave a tank this many pixels from its centre
+const MUZZLE_DISTANCE: f64 = 30.0;
+// Beams, and lines of sight from blasts, are checked every TRACE_STEP pixels along their length
+const TRACE_STEP: f64 = 2.0;
 
 // We'll make our Color type an RGBA8888 pixel.
 type Color = [u8; DEPTH];
@@ -36,6 +64,113 @@ pub trait Collider {
     fn set_pos(&mut self, x: i32, y: i32);
 }
 
+/// Which side a mobile, or whoever fired a projectile, is on
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Side {
+    Player,
+    Enemy,
+}
+
+/// What kind of harm a weapon does, for armor to resist
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DamageType {
+    // Solid shells
+    Kinetic,
+    // Blasts
+    Explosive,
+    // Beams
+    Energy,
+}
+
+/// How a tank responds to being driven (see Mobile::drive)
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Handling {
+    // Pixels per frame the tank's speed picks up each frame it's driven
+    pub accel: f32,
+    // Pixels per frame
+    pub max_speed: f32,
+    // Fraction of its velocity the tank loses each frame
+    pub friction: f32,
+}
+
+/*
+Armor makes a mobile take less damage than a weapon deals: first the resistance to the damage's
+type takes off a fraction of it, then plating takes off a flat amount. A hit always does at least 1
+damage, so nothing is immune.
+*/
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Armor {
+    pub plating: i32,
+    // Fractions of each type of damage ignored, from 0 to 1
+    pub kinetic: f64,
+    pub explosive: f64,
+    pub energy: f64,
+}
+
+impl Armor {
+    pub const NONE: Armor = Armor {
+        plating: 0,
+        kinetic: 0.0,
+        explosive: 0.0,
+        energy: 0.0,
+    };
+
+    /// How much of amount damage of type kind gets through
+    pub fn reduce(&self, amount: i32, kind: DamageType) -> i32 {
+        let resistance = match kind {
+            DamageType::Kinetic => self.kinetic,
+            DamageType::Explosive => self.explosive,
+            DamageType::Energy => self.energy,
+        };
+        let resisted = (amount as f64 * (1.0 - resistance.clamp(0.0, 1.0))).round() as i32;
+        (resisted - self.plating).max(1)
+    }
+}
+
+/*
+Everything handle_contact does to the game is reported as a ContactEvent, so sound, particles,
+scoring and stats can each react to what they care about without the collision code knowing they
+exist. Rects are where the object was when the event happened; objects that die are gone from
+their Vecs by the time handle_contact returns.
+*/
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ContactEvent {
+    // A projectile glanced off terrain; normal is the unit vector out of the face it hit
+    Bounce {
+        at: Vec2f,
+        normal: Vec2f,
+    },
+    // A destructible terrain block took damage and has hp left
+    TerrainDamaged {
+        rect: Rect,
+        hp: i32,
+    },
+    // A destructible terrain block was knocked out
+    TerrainDestroyed {
+        rect: Rect,
+    },
+    // A shot fired by `by` struck a mobile at `at`, taking amount hp off it after armor
+    MobileDamaged {
+        rect: Rect,
+        side: Side,
+        by: Side,
+        amount: i32,
+        kind: DamageType,
+        at: Vec2f,
+    },
+    // A mobile's hp reached 0
+    MobileKilled {
+        rect: Rect,
+        side: Side,
+        by: Side,
+    },
+    // A spent shell burst, hurting everything within radius of at that it could reach
+    Explosion {
+        at: Vec2f,
+        radius: f64,
+    },
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub(crate) struct Contact {
     a: ColliderID,
@@ -85,6 +220,25 @@ pub struct Mobile {
     pub vx: f32,
     pub vy: f32,
     pub hp: i32,
+    // What hp starts at
+    pub max_hp: i32,
+    pub armor: Armor,
+    // Direction the tank last moved in, in radians clockwise from the +x axis
+    pub facing: f64,
+    // How far the turret can turn either side of facing, in radians; None if it turns all the
+    // way round
+    pub traverse: Option<f64>,
+    // Pixels the tank can still drive this turn, and how many it gets at the start of each turn
+    pub fuel: f64,
+    pub max_fuel: f64,
+    // Where the tank was before it last moved; fuel is charged for how far it got from here once
+    // collisions have pushed it back
+    pub moved_from: (i32, i32),
+    // How the tank drives when it's steered; None for tanks whose AI sets their velocity directly
+    pub handling: Option<Handling>,
+    // Movement from earlier frames that hasn't added up to a whole pixel yet, for tanks with
+    // handling; the others move by their velocity rounded towards zero, as they always have
+    pub carry: (f32, f32),
     pub is_player: bool,
 }
 impl Collider for Mobile {
@@ -105,6 +259,15 @@ impl Mobile {
             vx: 0.0,
             vy: 0.0,
             hp,
+            max_hp: hp,
+            armor: Armor::NONE,
+            facing: 0.0,
+            traverse: ENEMY_TRAVERSE,
+            fuel: f64::INFINITY,
+            max_fuel: f64::INFINITY,
+            moved_from: (x, y),
+            handling: None,
+            carry: (0.0, 0.0),
             is_player: false,
         }
     }
@@ -115,10 +278,125 @@ impl Mobile {
             vx: 0.0,
             vy: 0.0,
             hp,
+            max_hp: hp,
+            armor: PLAYER_ARMOR,
+            facing: 0.0,
+            traverse: None,
+            fuel: PLAYER_FUEL,
+            max_fuel: PLAYER_FUEL,
+            moved_from: (x, y),
+            handling: Some(PLAYER_HANDLING),
+            carry: (0.0, 0.0),
             is_player: true,
         }
     }
 
+    pub fn side(&self) -> Side {
+        if self.is_player {
+            Side::Player
+        } else {
+            Side::Enemy
+        }
+    }
+
+    /// Drives the tank for a frame towards steer (any length; zero to coast) as its handling
+    /// allows; does nothing to tanks without handling
+    pub fn drive(&mut self, steer: Vec2f) {
+        let handling = match self.handling {
+            Some(handling) => handling,
+            None => return,
+        };
+        let length = steer.0.hypot(steer.1);
+        let (mut vx, mut vy) = (self.vx, self.vy);
+        if length > 0.0 {
+            vx += (steer.0 / length) as f32 * handling.accel;
+            vy += (steer.1 / length) as f32 * handling.accel;
+        }
+        vx *= 1.0 - handling.friction;
+        vy *= 1.0 - handling.friction;
+
+        let speed = vx.hypot(vy);
+        if speed > handling.max_speed {
+            vx *= handling.max_speed / speed;
+            vy *= handling.max_speed / speed;
+        } else if length == 0.0 && speed < STOP_SPEED {
+            vx = 0.0;
+            vy = 0.0;
+        }
+        self.vx = vx;
+        self.vy = vy;
+    }
+
+    /// Whole pixels to move the tank this frame; for tanks with handling, whatever's left over is
+    /// carried to the next
+    pub fn step(&mut self) -> (i32, i32) {
+        if self.handling.is_none() {
+            return (self.vx as i32, self.vy as i32);
+        }
+        let x = self.carry.0 + self.vx;
+        let y = self.carry.1 + self.vy;
+        self.carry = (x.fract(), y.fract());
+        (x.trunc() as i32, y.trunc() as i32)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.vx != 0.0 || self.vy != 0.0
+    }
+
+    /// Which of the four ways the tank's sprite can point (as named in its animations) is
+    /// closest to facing
+    pub fn heading(&self) -> &'static str {
+        let quarter = (self.facing / (PI / 2.0)).round() as usize % 4;
+        ["right", "down", "left", "up"][quarter]
+    }
+
+    /// Cuts the velocity down to what the fuel left allows, and notes where the tank is moving
+    /// from; call before moving it
+    pub fn ration_fuel(&mut self) {
+        let speed = (self.vx as f64).hypot(self.vy as f64);
+        if speed > self.fuel {
+            let scale = (self.fuel / speed) as f32;
+            self.vx *= scale;
+            self.vy *= scale;
+        }
+        self.moved_from = (self.rect.x, self.rect.y);
+    }
+
+    /// Uses up fuel for how far the tank really got since ration_fuel; call once collisions have
+    /// been resolved, so driving into a wall costs nothing
+    pub fn burn_fuel(&mut self) {
+        let moved = ((self.rect.x - self.moved_from.0) as f64)
+            .hypot((self.rect.y - self.moved_from.1) as f64);
+        self.fuel = (self.fuel - moved).max(0.0);
+        self.moved_from = (self.rect.x, self.rect.y);
+    }
+
+    /// Fills the tank up for a new turn; driving done in the last turn isn't charged to this one
+    pub fn refuel(&mut self) {
+        self.fuel = self.max_fuel;
+        self.moved_from = (self.rect.x, self.rect.y);
+    }
+
+    /// Turns the tank to face the way it's moving, if it's moving at all
+    pub fn face_velocity(&mut self) {
+        if self.is_moving() {
+            self.facing = normalize_angle((self.vy as f64).atan2(self.vx as f64));
+        }
+    }
+
+    /// aim, turned as little as needed to be within the turret's traverse, in [0, 2π)
+    pub fn clamp_aim(&self, aim: f64) -> f64 {
+        let aim = normalize_angle(aim);
+        match self.traverse {
+            None => aim,
+            Some(limit) => {
+                // How far aim is from facing, the short way round
+                let offset = normalize_angle(aim - self.facing + PI) - PI;
+                normalize_angle(self.facing + offset.clamp(-limit, limit))
+            }
+        }
+    }
+
     #[allow(dead_code)]
     pub fn move_pos(&mut self, dx: i32, dy: i32) {
         self.rect.x += dx;
@@ -136,8 +414,19 @@ pub struct Projectile {
     pub rrect: RotatedRect,
     vx: f64,
     vy: f64,
-    hp: i32,
+    // Times it can still glance off terrain; it's spent on the next terrain it hits after that
+    bounces_left: i32,
+    // Whether it has done what it's going to do, and is cleared out at the end of the frame
+    spent: bool,
     speed: f64,
+    // Who fired it
+    owner: Side,
+    weapon: &'static Weapon,
+    // How far the shot was charged, from 0 to 1
+    charge: f64,
+    // Pixels flown and frames spent flying so far
+    travelled: f64,
+    age: usize,
 }
 impl Collider for Projectile {
     fn move_pos(&mut self, dx: i32, dy: i32) {
@@ -151,14 +440,18 @@ impl Collider for Projectile {
     }
 }
 impl Projectile {
-    pub fn new(from: &Mobile, rotation: f64) -> Self {
-        let speed = 2.0;
+    /// A shell of weapon fired by from towards rotation; the more charged it is (from 0 to 1), the
+    /// faster it flies, or for lobbed shells the further
+    pub fn new(from: &Mobile, rotation: f64, weapon: &'static Weapon, charge: f64) -> Self {
+        let speed = match weapon.delivery {
+            Delivery::Lobbed { .. } => weapon.speed,
+            _ => weapon.speed * weapon::power(charge),
+        };
 
-        // Spawn projectile a distance of 20 away from Mobile, towards rotation
-        let x = (from.rect.x + from.rect.w as i32 / 2) as f64 + rotation.cos() * 30.0;
-        let y = (from.rect.y + from.rect.h as i32 / 2) as f64 + rotation.sin() * 30.0;
+        // Spawn projectile at the muzzle, towards rotation
+        let Vec2f(x, y) = muzzle(from, rotation);
 
-        // Projectile starts with velocity towards angle, with magnitude 3
+        // Projectile starts with velocity towards angle, at the weapon's speed
         let vx = rotation.cos() * speed;
         let vy = rotation.sin() * speed;
 
@@ -166,14 +459,64 @@ impl Projectile {
             rrect: RotatedRect {
                 x,
                 y,
-                w: 14,
-                h: 7,
+                w: weapon.size.0,
+                h: weapon.size.1,
                 rotation,
             },
             vx,
             vy,
-            hp: PROJ_MAX_BOUNCES,
+            bounces_left: weapon.bounces,
+            spent: false,
             speed,
+            owner: from.side(),
+            weapon,
+            charge,
+            travelled: 0.0,
+            age: 0,
+        }
+    }
+
+    // Counts a brush with terrain that corners_in of the projectile's corners are inside; returns
+    // whether it was a real hit, one that uses up a bounce (or the projectile, if it has none left)
+    fn strike_terrain(&mut self, corners_in: usize) -> bool {
+        // Shells that can't bounce are spent on the first terrain they touch at all
+        let hit = corners_in == 1 || (corners_in > 0 && self.weapon.bounces == 0);
+        if !hit || self.spent {
+            return false;
+        }
+        if self.bounces_left > 0 {
+            self.bounces_left -= 1;
+        } else {
+            self.spent = true;
+        }
+        true
+    }
+
+    // How far a lobbed projectile flies before it comes down
+    fn range(&self) -> Option<f64> {
+        match self.weapon.delivery {
+            Delivery::Lobbed { range } => Some(range * weapon::power(self.charge)),
+            _ => None,
+        }
+    }
+
+    /// Whether the projectile is lobbed and still in the air, above everything it flies over
+    pub fn airborne(&self) -> bool {
+        matches!(self.range(), Some(range) if self.travelled < range)
+    }
+
+    // Whether the projectile is lobbed and has come down
+    fn landed(&self) -> bool {
+        matches!(self.range(), Some(range) if self.travelled >= range)
+    }
+
+    /// How high a lobbed projectile is, from 0 on the ground to 1 at the top of its arc
+    pub fn height(&self) -> f64 {
+        match self.range() {
+            Some(range) if self.travelled < range => {
+                (self.travelled / range * std::f64::consts::PI).sin()
+            }
+            _ => 0.0,
         }
     }
 
@@ -195,9 +538,19 @@ impl Projectile {
     pub fn update_pos(&mut self) {
         self.rrect.x += self.vx;
         self.rrect.y += self.vy;
+        self.travelled += self.speed;
+        self.age += 1;
     }
 }
 
+/// Where shots fired by from towards rotation start
+pub fn muzzle(from: &Mobile, rotation: f64) -> Vec2f {
+    Vec2f(
+        (from.rect.x + from.rect.w as i32 / 2) as f64 + rotation.cos() * MUZZLE_DISTANCE,
+        (from.rect.y + from.rect.h as i32 / 2) as f64 + rotation.sin() * MUZZLE_DISTANCE,
+    )
+}
+
 pub struct Wall {
     rect: Rect,
 }
@@ -376,8 +729,8 @@ pub(crate) fn gather_contacts(
             }
         }
     }
-    // collide projs against mobiles
-    for (ai, a) in projs.iter().enumerate() {
+    // collide projs against mobiles; lobbed projectiles fly over everything
+    for (ai, a) in projs.iter().enumerate().filter(|(_, a)| !a.airborne()) {
         for (bi, b) in mobiles.iter().enumerate() {
             let b = &b.collider;
             let mut collide = false;
@@ -398,19 +751,10 @@ pub(crate) fn gather_contacts(
         }
     }
     // collide projs against terrains
-    for (ai, a) in projs.iter().enumerate() {
+    for (ai, a) in projs.iter().enumerate().filter(|(_, a)| !a.airborne()) {
         for (bi, b) in terrains.iter().enumerate() {
             let b = &b.collider;
-            if check_rotated_collision(
-                &a.rrect,
-                &RotatedRect {
-                    x: b.rect.x as f64 + b.rect.w as f64 / 2.0,
-                    y: b.rect.y as f64 + b.rect.h as f64 / 2.0,
-                    w: b.rect.w,
-                    h: b.rect.h,
-                    rotation: 0.0,
-                },
-            ) {
+            if near(&a.rrect, b.rect) && check_rotated_collision(&a.rrect, &unrotated(b.rect)) {
                 let contact = Contact {
                     a: ColliderID::Projectile(ai),
                     b: ColliderID::Terrain(bi),
@@ -423,6 +767,27 @@ pub(crate) fn gather_contacts(
     }
 }
 
+// rect as a RotatedRect, for checking against projectiles
+fn unrotated(rect: Rect) -> RotatedRect {
+    RotatedRect {
+        x: rect.x as f64 + rect.w as f64 / 2.0,
+        y: rect.y as f64 + rect.h as f64 / 2.0,
+        w: rect.w,
+        h: rect.h,
+        rotation: 0.0,
+    }
+}
+
+// Whether rect comes within reach of any part of rrect, however it's turned: a cheap test that
+// rules out most terrain before the exact (and much slower) check_rotated_collision
+fn near(rrect: &RotatedRect, rect: Rect) -> bool {
+    let reach = (rrect.w as f64).hypot(rrect.h as f64) / 2.0;
+    rrect.x + reach >= rect.x as f64
+        && rrect.x - reach <= (rect.x + rect.w as i32) as f64
+        && rrect.y + reach >= rect.y as f64
+        && rrect.y - reach <= (rect.y + rect.h as i32) as f64
+}
+
 fn check_rotated_collision(rrect_a: &RotatedRect, rrect_b: &RotatedRect) -> bool {
     // Separating axis theorem: like AABB but not AA
     //      Get axes (two for each rectangle)
@@ -493,110 +858,409 @@ fn check_rotated_collision(rrect_a: &RotatedRect, rrect_b: &RotatedRect) -> bool
 
 /*
 Modify the hp of the objects and remove unnecessary objects.
-Return a boolean indicating if the player is alive.
+Everything that happens is added to events; see ContactEvent.
+The player's mobile is never removed, even at 0 hp.
 */
 pub(crate) fn handle_contact(
     terrains: &mut Vec<Entity<Terrain>>,
     mobiles: &mut Vec<Entity<Mobile>>,
     projs: &mut Vec<Projectile>,
     contacts: &mut Vec<Contact>,
-) -> (bool, usize) {
+    events: &mut Vec<ContactEvent>,
+) {
     // Restitute before calculating hp to avoid restituting objects after they die
     restitute(terrains, mobiles, contacts);
 
+    // Projectiles that have flown for their whole lifetime, and lobbed ones that came down this
+    // frame, are spent
+    for proj in projs.iter_mut() {
+        if proj.landed() || proj.age >= proj.weapon.lifetime {
+            proj.spent = true;
+        }
+    }
+
     // We first modify the hp of the collision objects.
     for contact in contacts.iter() {
         match (contact.a, contact.b) {
             // PT collision
             (ColliderID::Projectile(a), ColliderID::Terrain(b)) => {
-                // How to reflect projectiles?
-                // We check which corner is touching the terrain
-                // If bottom or top corner, then flip across horizontal axis
-                // If left or right corner, then flip across vertical axis
-                let corners = projs[a].rrect.corners();
-
-                let x_max = corners
-                    .iter()
-                    .cloned()
-                    .max_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
-                    .unwrap()
-                    .0;
-                let x_min = corners
-                    .iter()
-                    .cloned()
-                    .min_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
-                    .unwrap()
-                    .0;
-                let y_max = corners
-                    .iter()
-                    .cloned()
-                    .max_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
-                    .unwrap()
-                    .1;
-                let y_min = corners
-                    .iter()
-                    .cloned()
-                    .min_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
-                    .unwrap()
-                    .1;
-
-                let mut corners_in = 0;
-                for (i, c) in corners.iter().enumerate() {
-                    if terrains[b].collider.rect.contains_f(c) {
-                        corners_in += 1;
-                        /*
-                        if c.0 == x_max {
-                            println!("x_max");
-                        } else if c.0 == x_min {
-                            println!("x_min");
-                        } else if c.1 == y_max {
-                            println!("y_max");
-                        } else if c.1 == y_min {
-                            println!("y_min");
-                        }
-                        */
+                let t_rect = terrains[b].collider.rect;
+                let (corners_in, touching, normal) = glance(&mut projs[a], t_rect);
+                let weapon = projs[a].weapon;
+                // As with mobiles, shells with a blast radius only do damage when they burst
+                if projs[a].strike_terrain(corners_in)
+                    && terrains[b].collider.destructible
+                    && weapon.blast_radius <= 0.0
+                {
+                    terrains[b].collider.hp -= weapon.terrain_damage;
+                    if terrains[b].collider.hp > 0 {
+                        events.push(ContactEvent::TerrainDamaged {
+                            rect: t_rect,
+                            hp: terrains[b].collider.hp,
+                        });
                     }
                 }
                 if corners_in == 1 {
-                    projs[a].hp -= 1;
-                    if terrains[b].collider.destructible {
-                        terrains[b].collider.hp -= 1;
-                    }
-                }
-
-                // TODO: this isn't correct if more than 1 corner overlaps the Terrain
-                for c in corners.iter() {
-                    if terrains[b].collider.rect.contains_f(c) {
-                        if (c.0 - x_max).abs() < 0.0001 || (c.0 - x_min).abs() < 0.0001 {
-                            let old_rot = projs[a].rrect.rotation;
-                            projs[a].set_rotation(std::f64::consts::PI - old_rot);
-                        } else {
-                            let old_rot = projs[a].rrect.rotation;
-                            projs[a].set_rotation(2. * std::f64::consts::PI - old_rot);
-                        }
-                    }
+                    events.push(ContactEvent::Bounce {
+                        at: touching,
+                        normal,
+                    });
                 }
             }
             //PM collisions damages the mobile and erase the projectile.
             (ColliderID::Projectile(a), ColliderID::Mobile(b)) => {
-                if mobiles[b].collider.hp >= projs[a].hp {
-                    mobiles[b].collider.hp -= projs[a].hp;
-                } else {
-                    mobiles[b].collider.hp = 0;
+                // A projectile that already hit something this frame makes no difference, and
+                // one with a blast radius only does damage when it bursts
+                let weapon = projs[a].weapon;
+                if !projs[a].spent && weapon.blast_radius <= 0.0 {
+                    let at = Vec2f(projs[a].rrect.x, projs[a].rrect.y);
+                    damage_mobile(
+                        &mut mobiles[b].collider,
+                        weapon.damage_at(projs[a].travelled),
+                        weapon.kind,
+                        projs[a].owner,
+                        at,
+                        events,
+                    );
                 }
-                projs[a].hp = 0;
+                projs[a].spent = true;
             }
             _ => {}
         }
     }
-    let player_is_alive = mobiles[0].collider.hp != 0;
+
+    // Spent shells with a blast radius burst where they are
+    for proj in projs.iter().filter(|p| p.spent) {
+        if proj.weapon.blast_radius > 0.0 {
+            let at = Vec2f(proj.rrect.x, proj.rrect.y);
+            explode(terrains, mobiles, at, proj.weapon, proj.owner, events);
+        }
+    }
+    remove_dead(terrains, mobiles, events);
+    projs.retain(|proj| !proj.spent);
+}
+
+/*
+Where shot would go, as points a few pixels apart covering up to length pixels of its path. It flies
+and glances off terrain just as it would in handle_contact, but hurts nothing; the path ends where
+it would be spent. Mobiles move about too much for a path to be kept around if it stopped at them,
+so they're left out here: see path_to_mobile.
+*/
+pub(crate) fn trace_path(
+    terrains: &[Entity<Terrain>],
+    shot: &Projectile,
+    length: f64,
+) -> Vec<Vec2f> {
+    let mut proj = shot.clone();
+    let mut path = vec![Vec2f(proj.rrect.x, proj.rrect.y)];
+    let mut flown = 0.0;
+    while flown < length {
+        if !proj.airborne() {
+            // Like gather_contacts, find everything it touches before glancing off any of it
+            let touching: Vec<Rect> = terrains
+                .iter()
+                .map(|t| t.collider.rect)
+                .filter(|r| near(&proj.rrect, *r))
+                .filter(|r| check_rotated_collision(&proj.rrect, &unrotated(*r)))
+                .collect();
+            for rect in touching {
+                let (corners_in, _, _) = glance(&mut proj, rect);
+                proj.strike_terrain(corners_in);
+            }
+        }
+        if proj.spent || proj.landed() || proj.age >= proj.weapon.lifetime {
+            break;
+        }
+
+        let last = Vec2f(proj.rrect.x, proj.rrect.y);
+        proj.update_pos();
+        let at = Vec2f(proj.rrect.x, proj.rrect.y);
+        flown += (at.0 - last.0).hypot(at.1 - last.1);
+        path.push(at);
+    }
+    path
+}
+
+/// How many points of path (from trace_path, for a shell flying low enough to hit tanks) the shell
+/// gets through, up to and including the first where it would hit a mobile. The shell points along
+/// its path, so it hits when its nose (half its length ahead) reaches one
+pub(crate) fn path_to_mobile(path: &[Vec2f], mobiles: &[Entity<Mobile>], length: u16) -> usize {
+    let nose = length as f64 / 2.0;
+    let hits = |p: &Vec2f| {
+        mobiles.iter().any(|m| {
+            let r = m.collider.rect;
+            p.0 >= r.x as f64 - nose
+                && p.0 <= (r.x + r.w as i32) as f64 + nose
+                && p.1 >= r.y as f64 - nose
+                && p.1 <= (r.y + r.h as i32) as f64 + nose
+        })
+    };
+    path.iter().position(hits).map_or(path.len(), |i| i + 1)
+}
+
+/// Where a beam from from towards angle would go, as for trace_path: straight on for length pixels
+/// or until it reaches terrain
+pub(crate) fn beam_path(
+    terrains: &[Entity<Terrain>],
+    from: Vec2f,
+    angle: f64,
+    length: f64,
+) -> Vec<Vec2f> {
+    (0..(length / TRACE_STEP) as usize)
+        .map(|i| {
+            let dist = i as f64 * TRACE_STEP;
+            Vec2f(from.0 + angle.cos() * dist, from.1 + angle.sin() * dist)
+        })
+        .take_while(|at| !terrains.iter().any(|t| t.collider.rect.contains_f(at)))
+        .collect()
+}
+
+/*
+Glances proj off the terrain block at rect, which it's touching: returns how many of proj's corners
+are inside the block, the last one found inside and the unit vector out of the face it hit.
+*/
+fn glance(proj: &mut Projectile, rect: Rect) -> (usize, Vec2f, Vec2f) {
+    // How to reflect projectiles?
+    // We check which corner is touching the terrain
+    // If bottom or top corner, then flip across horizontal axis
+    // If left or right corner, then flip across vertical axis
+    let corners = proj.rrect.corners();
+
+    let x_max = corners
+        .iter()
+        .cloned()
+        .max_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
+        .unwrap()
+        .0;
+    let x_min = corners
+        .iter()
+        .cloned()
+        .min_by(|a, b| a.0.partial_cmp(&b.0).expect("Tried to compare a NaN"))
+        .unwrap()
+        .0;
+
+    let mut corners_in = 0;
+    let mut touching = Vec2f(proj.rrect.x, proj.rrect.y);
+    for c in corners.iter() {
+        if rect.contains_f(c) {
+            corners_in += 1;
+            touching = *c;
+        }
+    }
+    // The face hit is on whichever side of the terrain's centre the projectile is
+    let (px, py) = (proj.rrect.x, proj.rrect.y);
+    let away_x = (px - (rect.x as f64 + rect.w as f64 / 2.0)).signum();
+    let away_y = (py - (rect.y as f64 + rect.h as f64 / 2.0)).signum();
+    let mut normal = Vec2f(0.0, away_y);
+    // TODO: this isn't correct if more than 1 corner overlaps the Terrain
+    for c in corners.iter() {
+        if rect.contains_f(c) {
+            if (c.0 - x_max).abs() < 0.0001 || (c.0 - x_min).abs() < 0.0001 {
+                let old_rot = proj.rrect.rotation;
+                proj.set_rotation(std::f64::consts::PI - old_rot);
+                normal = Vec2f(away_x, 0.0);
+            } else {
+                let old_rot = proj.rrect.rotation;
+                proj.set_rotation(2. * std::f64::consts::PI - old_rot);
+                normal = Vec2f(0.0, away_y);
+            }
+        }
+    }
+    (corners_in, touching, normal)
+}
+
+/*
+Fires a beam from `from` towards angle. It hurts every mobile it passes through and stops at the
+first terrain it reaches (damaging it, if it's destructible) or at the end of its range; returns
+where it stopped.
+*/
+pub(crate) fn fire_beam(
+    terrains: &mut Vec<Entity<Terrain>>,
+    mobiles: &mut Vec<Entity<Mobile>>,
+    from: Vec2f,
+    angle: f64,
+    weapon: &Weapon,
+    by: Side,
+    events: &mut Vec<ContactEvent>,
+) -> Vec2f {
+    let range = match weapon.delivery {
+        Delivery::Beam { range } => range,
+        _ => 0.0,
+    };
+    let mut hit = vec![false; mobiles.len()];
+    let mut dist = 0.0;
+    let mut at = from;
+    while dist < range {
+        at = Vec2f(from.0 + angle.cos() * dist, from.1 + angle.sin() * dist);
+        if let Some(t) = terrains
+            .iter_mut()
+            .find(|t| t.collider.rect.contains_f(&at))
+        {
+            let t = &mut t.collider;
+            if t.destructible {
+                t.hp -= weapon.terrain_damage;
+                if t.hp > 0 {
+                    events.push(ContactEvent::TerrainDamaged {
+                        rect: t.rect,
+                        hp: t.hp,
+                    });
+                }
+            }
+            break;
+        }
+        for (i, m) in mobiles.iter_mut().enumerate() {
+            if !hit[i] && m.collider.rect.contains_f(&at) {
+                hit[i] = true;
+                let amount = weapon.damage_at(dist);
+                damage_mobile(&mut m.collider, amount, weapon.kind, by, at, events);
+            }
+        }
+        dist += TRACE_STEP;
+    }
+    remove_dead(terrains, mobiles, events);
+    at
+}
+
+// Takes amount damage of type kind, less target's armor, off target's hp and reports what it did
+fn damage_mobile(
+    target: &mut Mobile,
+    amount: i32,
+    kind: DamageType,
+    by: Side,
+    at: Vec2f,
+    events: &mut Vec<ContactEvent>,
+) {
+    let amount = target.armor.reduce(amount, kind).min(target.hp);
+    // A mobile that's already dead can't be hurt any more
+    if amount <= 0 {
+        return;
+    }
+    target.hp -= amount;
+    let (rect, side) = (target.rect, target.side());
+    events.push(ContactEvent::MobileDamaged {
+        rect,
+        side,
+        by,
+        amount,
+        kind,
+        at,
+    });
+    if target.hp == 0 {
+        events.push(ContactEvent::MobileKilled { rect, side, by });
+    }
+}
+
+/*
+Bursts a shell of weapon at at. Every mobile and destructible terrain block any part of which is
+within the blast radius is hurt, unless indestructible terrain stands between it and the burst.
+Damage falls off evenly from full at the burst to nothing at the edge of the blast, but anything
+caught in it takes at least 1.
+*/
+fn explode(
+    terrains: &mut [Entity<Terrain>],
+    mobiles: &mut [Entity<Mobile>],
+    at: Vec2f,
+    weapon: &Weapon,
+    by: Side,
+    events: &mut Vec<ContactEvent>,
+) {
+    let radius = weapon.blast_radius;
+    events.push(ContactEvent::Explosion { at, radius });
+    let falloff =
+        |amount: i32, dist: f64| ((amount as f64 * (1.0 - dist / radius)).ceil() as i32).max(1);
+
+    // Work out what the blast reaches before changing anything, so blocks it knocks out don't
+    // let it through to what's behind them
+    let reached = |r: Rect| {
+        let nearest = nearest_point(r, at);
+        let dist = (nearest.0 - at.0).hypot(nearest.1 - at.1);
+        if dist <= radius && line_of_sight(terrains, at, nearest) {
+            Some((nearest, dist))
+        } else {
+            None
+        }
+    };
+    let mobiles_hit: Vec<_> = mobiles
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| reached(m.collider.rect).map(|hit| (i, hit)))
+        .collect();
+    let terrains_hit: Vec<_> = terrains
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.collider.destructible)
+        .filter_map(|(i, t)| reached(t.collider.rect).map(|(_, dist)| (i, dist)))
+        .collect();
+
+    for (i, (nearest, dist)) in mobiles_hit {
+        let amount = falloff(weapon.damage, dist);
+        damage_mobile(
+            &mut mobiles[i].collider,
+            amount,
+            weapon.kind,
+            by,
+            nearest,
+            events,
+        );
+    }
+    for (i, dist) in terrains_hit {
+        let t = &mut terrains[i].collider;
+        t.hp -= falloff(weapon.terrain_damage, dist);
+        if t.hp > 0 {
+            events.push(ContactEvent::TerrainDamaged {
+                rect: t.rect,
+                hp: t.hp,
+            });
+        }
+    }
+}
+
+// The point in or on r closest to p
+fn nearest_point(r: Rect, p: Vec2f) -> Vec2f {
+    Vec2f(
+        p.0.clamp(r.x as f64, (r.x + r.w as i32) as f64),
+        p.1.clamp(r.y as f64, (r.y + r.h as i32) as f64),
+    )
+}
+
+// Whether the line from from to to stays out of indestructible terrain; running along the edge of a
+// block doesn't count as going through it, and nor does leaving a block that from is inside (as
+// when a lobbed shell comes down on a wall)
+fn line_of_sight(terrains: &[Entity<Terrain>], from: Vec2f, to: Vec2f) -> bool {
+    let inside = |r: Rect, p: Vec2f| {
+        p.0 > r.x as f64
+            && p.0 < (r.x + r.w as i32) as f64
+            && p.1 > r.y as f64
+            && p.1 < (r.y + r.h as i32) as f64
+    };
+    let walls: Vec<Rect> = terrains
+        .iter()
+        .map(|terrain| &terrain.collider)
+        .filter(|t| !t.destructible && !inside(t.rect, from))
+        .map(|t| t.rect)
+        .collect();
+
+    let steps = ((to.0 - from.0).hypot(to.1 - from.1) / TRACE_STEP).ceil() as usize;
+    (0..=steps).all(|i| {
+        let t = i as f64 / steps.max(1) as f64;
+        let p = Vec2f(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+        !walls.iter().any(|&r| inside(r, p))
+    })
+}
+
+// Clears out destroyed terrain and dead mobiles (other than the player's)
+fn remove_dead(
+    terrains: &mut Vec<Entity<Terrain>>,
+    mobiles: &mut Vec<Entity<Mobile>>,
+    events: &mut Vec<ContactEvent>,
+) {
+    events.extend(terrains.iter().filter(|t| t.collider.hp <= 0).map(|t| {
+        ContactEvent::TerrainDestroyed {
+            rect: t.collider.rect,
+        }
+    }));
     terrains.retain(|terrain| terrain.collider.hp > 0);
-    let ori = mobiles.len();
     mobiles.retain(|mobile| mobile.collider.hp > 0 || mobile.collider.is_player);
-    let new = mobiles.len();
-    projs.retain(|proj| proj.hp > 0);
-
-    (player_is_alive, ori - new)
 }
 
 fn restitute(
@@ -612,9 +1276,11 @@ fn restitute(
 
             if contact.mtv.0 != 0 {
                 dynamics[ai].collider.vx = 0.0;
+                dynamics[ai].collider.carry.0 = 0.0;
             }
             if contact.mtv.1 != 0 {
                 dynamics[ai].collider.vy = 0.0;
+                dynamics[ai].collider.carry.1 = 0.0;
             }
         }
     }
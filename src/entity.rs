@@ -46,6 +46,6 @@ impl<T: Collider> Entity<T> {
     }
 
     pub fn anim_trans(&mut self, input: &str, frame: usize) {
-        self.sprite.animation_sm.input(input, frame);
+        self.sprite.input(input, frame);
     }
 }
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::assets::AssetError;
+use crate::atlas::Atlas;
+use crate::screen::{BlitStyle, Screen};
+use crate::texture::Texture;
+use crate::types::{Rect, Rgba, Vec2i};
+
+/// Which part of each line of text lines up with the position it's drawn at
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+    Left,
+    Centre,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextStyle {
+    pub align: Align,
+    pub colour: Rgba,
+    // Lines longer than this many pixels are broken between words (or inside words that don't fit
+    // on a line of their own)
+    pub wrap: Option<i32>,
+}
+
+impl TextStyle {
+    pub const PLAIN: TextStyle = TextStyle {
+        align: Align::Left,
+        colour: Rgba(0, 0, 0, 255),
+        wrap: None,
+    };
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self::PLAIN
+    }
+}
+
+/*
+A bitmap font is a sprite sheet of glyphs whose atlas has a frame called glyph/<c> for each
+character c it can draw, plus:
+
+    glyph/space     a blank frame; its width is how far a space moves along
+    glyph/fallback  drawn in place of any character without a glyph of its own
+
+Each glyph moves the next one along by the width of its frame, so narrow marks like '.' can take
+up less room than letters. The sheet is drawn as dark ink on a clear background; it's turned into
+white ink when loaded so text can be drawn in any colour.
+*/
+pub struct Font {
+    texture: Rc<Texture>,
+    glyphs: HashMap<char, Rect>,
+    fallback: Rect,
+    line_height: i32,
+}
+
+impl Font {
+    /// Loads a font sheet along with the atlas descriptor next to it (same name, .atlas extension)
+    pub fn with_file(path: &Path) -> Result<Self, AssetError> {
+        let texture = Rc::new(Texture::with_file(path).into_ink());
+        let atlas_path = path.with_extension("atlas");
+        let atlas = Atlas::with_file(&atlas_path)?;
+        atlas.validate(&texture)?;
+
+        let mut glyphs = HashMap::new();
+        for name in atlas.names() {
+            let c = match name.strip_prefix("glyph/") {
+                None | Some("fallback") => continue,
+                Some("space") => ' ',
+                Some(c) if c.chars().count() == 1 => c.chars().next().unwrap(),
+                Some(_) => {
+                    return Err(AssetError::Parse {
+                        origin: atlas_path.display().to_string(),
+                        line: 0,
+                        msg: format!("{:?} isn't a glyph for a single character", name),
+                    })
+                }
+            };
+            glyphs.insert(c, atlas.frame(name)?.rect);
+        }
+        let fallback = atlas.frame("glyph/fallback")?.rect;
+        let line_height = glyphs
+            .values()
+            .chain(std::iter::once(&fallback))
+            .map(|r| r.h as i32)
+            .max()
+            .unwrap();
+
+        Ok(Self {
+            texture,
+            glyphs,
+            fallback,
+            line_height,
+        })
+    }
+
+    pub fn line_height(&self) -> i32 {
+        self.line_height
+    }
+
+    // Region of the sheet to draw for c
+    fn glyph(&self, c: char) -> Rect {
+        self.glyphs.get(&c).copied().unwrap_or(self.fallback)
+    }
+
+    /// Width in pixels of a single line of text
+    pub fn width(&self, line: &str) -> i32 {
+        line.chars().map(|c| self.glyph(c).w as i32).sum()
+    }
+
+    /// Splits text into the lines it's drawn as
+    pub fn lines(&self, text: &str, wrap: Option<i32>) -> Vec<String> {
+        let width = match wrap {
+            Some(width) => width,
+            None => return text.split('\n').map(|l| l.to_string()).collect(),
+        };
+
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let joined = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{} {}", line, word)
+                };
+                if self.width(&joined) <= width {
+                    line = joined;
+                    continue;
+                }
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                // Whatever doesn't fit now is a word longer than a whole line, so break it up
+                for c in word.chars() {
+                    if !line.is_empty() && self.width(&line) + self.glyph(c).w as i32 > width {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    line.push(c);
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Size in pixels of the block text would take up when drawn
+    pub fn measure(&self, text: &str, wrap: Option<i32>) -> Vec2i {
+        let lines = self.lines(text, wrap);
+        Vec2i(
+            lines.iter().map(|l| self.width(l)).max().unwrap_or(0),
+            lines.len() as i32 * self.line_height,
+        )
+    }
+
+    /// Draws text with the top of its first line at pos.1; pos.0 is where each line's left edge,
+    /// centre or right edge goes, depending on style.align
+    pub fn draw(&self, screen: &mut Screen, text: &str, pos: Vec2i, style: TextStyle) {
+        let blit_style = BlitStyle {
+            tint: style.colour,
+            ..BlitStyle::PLAIN
+        };
+        for (i, line) in self.lines(text, style.wrap).iter().enumerate() {
+            let mut x = match style.align {
+                Align::Left => pos.0,
+                Align::Centre => pos.0 - self.width(line) / 2,
+                Align::Right => pos.0 - self.width(line),
+            };
+            let y = pos.1 + i as i32 * self.line_height;
+            for c in line.chars() {
+                let glyph = self.glyph(c);
+                if c != ' ' {
+                    screen.bitblt_styled(&self.texture, glyph, Vec2i(x, y), blit_style);
+                }
+                x += glyph.w as i32;
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::collision::Mobile;
+use crate::entity::Entity;
+use crate::font::{Align, Font, TextStyle};
+use crate::render::{DrawCmd, DrawList, RenderLayer};
+use crate::screen::Screen;
+use crate::types::{Rect, Rgba, Vec2i};
+
+// Gap between HUD readouts and the edges of the screen
+const MARGIN: i32 = 8;
+// Space around the text on a readout's backing panel
+const PADDING: i32 = 4;
+const PANEL_COLOUR: Rgba = Rgba(0, 0, 0, 140);
+const TEXT_COLOUR: Rgba = Rgba(255, 255, 255, 255);
+const FUEL_COLOUR: Rgba = Rgba(80, 170, 240, 255);
+
+// HP bars over tanks are this tall and sit this far above them
+const TANK_BAR_HEIGHT: u16 = 4;
+const TANK_BAR_GAP: i32 = 4;
+// Size of the player's HP and reload bars
+const BAR_SIZE: (u16, u16) = (100, 10);
+
+/// Everything the HUD shows, gathered up by the game each frame
+pub struct HudState<'a> {
+    pub score: usize,
+    pub hp: i32,
+    pub max_hp: i32,
+    // Pixels the player's tank can still drive this turn, out of max_fuel
+    pub fuel: f64,
+    pub max_fuel: f64,
+    // Whose turn it is, or None between turns (e.g. while the player's tank blows up)
+    pub turn: Option<&'a str>,
+    // Name of the weapon the player has picked
+    pub weapon: &'a str,
+    // How far the player has charged their shot, from 0 to 1, while they're charging one
+    pub charge: Option<f64>,
+    pub loaded: bool,
+    // How far through reloading the player is, from 0 to 1
+    pub reload: f64,
+}
+
+/// Submits an HP bar above every tank, in world coordinates so it moves and zooms with the tank
+pub fn hp_bars(list: &mut DrawList, tanks: &[Entity<Mobile>]) {
+    for tank in tanks.iter() {
+        let r = tank.collider.rect;
+        let bar = Rect {
+            x: r.x,
+            y: r.y - TANK_BAR_GAP - TANK_BAR_HEIGHT as i32,
+            w: r.w,
+            h: TANK_BAR_HEIGHT,
+        };
+        for (i, (rect, col)) in bar_rects(bar, tank.collider.hp, tank.collider.max_hp)
+            .into_iter()
+            .enumerate()
+        {
+            list.push(RenderLayer::Overlay, i as i32, DrawCmd::Rect(rect, col));
+        }
+    }
+}
+
+/// Draws the HUD over the game, in screen coordinates; each readout keeps to its own corner
+pub fn draw(screen: &mut Screen, font: &Font, hud: &HudState) {
+    let (w, h) = screen.size();
+    let (w, h) = (w as i32, h as i32);
+    let line = font.line_height();
+
+    // Top left: score
+    text_panel(
+        screen,
+        font,
+        &format!("Score: {}", hud.score),
+        Vec2i(MARGIN, MARGIN),
+        Align::Left,
+    );
+
+    // Top right: whose turn it is
+    if let Some(owner) = hud.turn {
+        text_panel(screen, font, owner, Vec2i(w - MARGIN, MARGIN), Align::Right);
+    }
+
+    // Bottom left: the player's health
+    let hp_top = h - MARGIN - line;
+    let hp_label = format!("HP: {}/{}", hud.hp.max(0), hud.max_hp);
+    text_panel(screen, font, &hp_label, Vec2i(MARGIN, hp_top), Align::Left);
+    let bar = Rect {
+        x: MARGIN + font.width(&hp_label) + PADDING * 3,
+        y: hp_top + (line - BAR_SIZE.1 as i32) / 2,
+        w: BAR_SIZE.0,
+        h: BAR_SIZE.1,
+    };
+    for (rect, col) in bar_rects(bar, hud.hp, hud.max_hp) {
+        screen.rect(rect, col);
+    }
+    // Above it, how far the tank can still drive this turn
+    let fuel_top = hp_top - line - PADDING * 3;
+    text_panel(screen, font, "Fuel", Vec2i(MARGIN, fuel_top), Align::Left);
+    let fuel_bar = Rect {
+        y: fuel_top + (line - BAR_SIZE.1 as i32) / 2,
+        ..bar
+    };
+    let fuel = (hud.fuel / hud.max_fuel.max(1.0)).clamp(0.0, 1.0);
+    screen.rect(fuel_bar, PANEL_COLOUR);
+    screen.rect(
+        Rect {
+            w: (fuel_bar.w as f64 * fuel).round() as u16,
+            ..fuel_bar
+        },
+        FUEL_COLOUR,
+    );
+
+    // Bottom right: which weapon is picked, and whether it's ready
+    text_panel(
+        screen,
+        font,
+        &format!("Weapon: {} (Q/E)", hud.weapon),
+        Vec2i(w - MARGIN, hp_top - line - PADDING * 3),
+        Align::Right,
+    );
+    // While charging a shot, the reload readout shows how powerful it will be instead
+    let reload_label = match (hud.charge, hud.loaded) {
+        (Some(_), _) => "Power",
+        (None, true) => "Loaded",
+        (None, false) => "Reloading",
+    };
+    let label_right = w - MARGIN - BAR_SIZE.0 as i32 - PADDING * 3;
+    text_panel(
+        screen,
+        font,
+        reload_label,
+        Vec2i(label_right, hp_top),
+        Align::Right,
+    );
+    let bar = Rect {
+        x: w - MARGIN - BAR_SIZE.0 as i32,
+        ..bar
+    };
+    let (progress, col) = match (hud.charge, hud.loaded) {
+        (Some(charge), _) => (charge, Rgba(240, 110, 40, 255)),
+        (None, true) => (1.0, Rgba(240, 200, 40, 255)),
+        (None, false) => (hud.reload, Rgba(160, 160, 160, 255)),
+    };
+    screen.rect(bar, PANEL_COLOUR);
+    screen.rect(
+        Rect {
+            w: (bar.w as f64 * progress.clamp(0.0, 1.0)).round() as u16,
+            ..bar
+        },
+        col,
+    );
+}
+
+// Draws text on a dark panel so it reads over any terrain; pos is as for Font::draw
+fn text_panel(screen: &mut Screen, font: &Font, text: &str, pos: Vec2i, align: Align) {
+    let size = font.measure(text, None);
+    let left = match align {
+        Align::Left => pos.0,
+        Align::Centre => pos.0 - size.0 / 2,
+        Align::Right => pos.0 - size.0,
+    };
+    screen.rect(
+        Rect {
+            x: left - PADDING,
+            y: pos.1 - PADDING,
+            w: (size.0 + PADDING * 2) as u16,
+            h: (size.1 + PADDING * 2) as u16,
+        },
+        PANEL_COLOUR,
+    );
+    font.draw(
+        screen,
+        text,
+        pos,
+        TextStyle {
+            align,
+            colour: TEXT_COLOUR,
+            ..TextStyle::PLAIN
+        },
+    );
+}
+
+// Backing and fill of an HP bar, coloured by how much health is left
+fn bar_rects(bar: Rect, hp: i32, max_hp: i32) -> Vec<(Rect, Rgba)> {
+    let frac = (hp.max(0) as f64 / max_hp.max(1) as f64).min(1.0);
+    let col = if frac > 0.5 {
+        Rgba(60, 200, 60, 255)
+    } else if frac > 0.25 {
+        Rgba(230, 200, 40, 255)
+    } else {
+        Rgba(220, 50, 40, 255)
+    };
+    vec![
+        (
+            Rect {
+                x: bar.x - 1,
+                y: bar.y - 1,
+                w: bar.w + 2,
+                h: bar.h + 2,
+            },
+            PANEL_COLOUR,
+        ),
+        (
+            Rect {
+                w: (bar.w as f64 * frac).round() as u16,
+                ..bar
+            },
+            col,
+        ),
+    ]
+}
@@ -0,0 +1,121 @@
+use crate::types::Vec2i;
+
+pub const TILE_SZ: usize = 16;
+
+/// Special tile ID meaning "no tile here"
+pub const NO_TILE: i32 = -1;
+
+/// A level layout before it gets turned into entities.
+/// Both the hand-made map and the maze generator produce one of these, and
+/// `assets::level_walls` turns it into terrain.
+pub struct LevelGrid {
+    /// Width and height in tiles
+    pub dims: (usize, usize),
+    /// Row-major tile IDs (see `assets::get_tile_rect`), NO_TILE for empty space
+    pub tile_ids: Vec<i32>,
+    /// Row-major flags saying which tiles can be shot away
+    pub destructible: Vec<bool>,
+    /// Pixel coordinates of open spots where a tank can be centred.
+    /// The first one is where the player starts.
+    pub spawns: Vec<Vec2i>,
+}
+
+impl LevelGrid {
+    /// Build a grid from a solid/empty mask, picking the tile ID of each solid
+    /// tile from its neighbours.
+    pub fn from_solid(
+        dims: (usize, usize),
+        solid: &[bool],
+        destructible: Vec<bool>,
+        spawns: Vec<Vec2i>,
+    ) -> Self {
+        assert_eq!(dims.0 * dims.1, solid.len());
+        assert_eq!(solid.len(), destructible.len());
+
+        LevelGrid {
+            dims,
+            tile_ids: autotile(dims, solid),
+            destructible,
+            spawns,
+        }
+    }
+}
+
+/// Returns the tile ID for every tile of a solid/empty mask.
+/// Tiles outside the grid count as solid, so the map edge never gets an edge tile.
+///     0 = top-left corner, 1 = top-right corner, 2 = bottom-right corner, 3 = bottom-left corner
+///     4 = top edge, 5 = right edge, 6 = bottom edge, 7 = left edge
+///     8 = top-left inner corner, 9 = top-right inner corner, 10 = bottom-right inner corner, 11 = bottom-left inner corner
+///     12 = center
+pub fn autotile(dims: (usize, usize), solid: &[bool]) -> Vec<i32> {
+    let (w, h) = (dims.0 as i32, dims.1 as i32);
+    let open = |x: i32, y: i32| -> bool {
+        if x < 0 || y < 0 || x >= w || y >= h {
+            false
+        } else {
+            !solid[(y * w + x) as usize]
+        }
+    };
+
+    let mut tile_ids = vec![NO_TILE; solid.len()];
+    for y in 0..h {
+        for x in 0..w {
+            if open(x, y) {
+                continue;
+            }
+            let (n, e, s, wst) = (
+                open(x, y - 1),
+                open(x + 1, y),
+                open(x, y + 1),
+                open(x - 1, y),
+            );
+            tile_ids[(y * w + x) as usize] = if n && wst {
+                0
+            } else if n && e {
+                1
+            } else if s && e {
+                2
+            } else if s && wst {
+                3
+            } else if n {
+                4
+            } else if e {
+                5
+            } else if s {
+                6
+            } else if wst {
+                7
+            } else if open(x - 1, y - 1) {
+                8
+            } else if open(x + 1, y - 1) {
+                9
+            } else if open(x + 1, y + 1) {
+                10
+            } else if open(x - 1, y + 1) {
+                11
+            } else {
+                12
+            };
+        }
+    }
+
+    tile_ids
+}
+
+/// Finds open 2x2 tile squares on a coarse grid, for maps that don't list their own spawns.
+pub fn find_spawns(dims: (usize, usize), tile_ids: &[i32], step: usize) -> Vec<Vec2i> {
+    let mut spawns = vec![];
+    for y in (1..dims.1.saturating_sub(2)).step_by(step) {
+        for x in (1..dims.0.saturating_sub(2)).step_by(step) {
+            let empty =
+                (0..2).all(|dy| (0..2).all(|dx| tile_ids[(y + dy) * dims.0 + x + dx] == NO_TILE));
+            if empty {
+                spawns.push(Vec2i(
+                    ((x + 1) * TILE_SZ) as i32,
+                    ((y + 1) * TILE_SZ) as i32,
+                ));
+            }
+        }
+    }
+    spawns
+}
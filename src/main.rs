@@ -11,24 +11,29 @@ use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
 mod screen;
-use screen::Screen;
+use screen::{BlitStyle, Sampling, Screen, Transform};
 
 mod collision;
-use collision::{Collider, Contact, Mobile, Projectile, Terrain, Wall};
+use collision::{Collider, Contact, ContactEvent, Mobile, Projectile, Side, Terrain, Wall};
 
 mod entity;
 use entity::Entity;
 
 mod texture;
-use texture::Texture;
 
 mod tiles;
 use tiles::{Tile, Tilemap, Tileset, TILE_SZ};
 
+mod anim_graph;
 mod animation;
 
+mod atlas;
+use atlas::SpriteSheet;
+
 mod sprite;
-use sprite::*;
+
+mod render;
+use render::{DrawCmd, DrawList, RenderLayer};
 
 mod types;
 use types::*;
@@ -36,18 +41,72 @@ use types::*;
 mod assets;
 use assets::*;
 
+mod level;
+use level::LevelGrid;
+
+mod maze;
+use maze::MazeConfig;
+
+mod theme;
+use theme::Theme;
+
+mod camera;
+use camera::Camera;
+
+mod font;
+use font::Font;
+
+mod hud;
+use hud::HudState;
+
+mod menu;
+use menu::{Menu, MenuAction, Settings};
+
+mod particles;
+use particles::Particles;
+
+mod audio;
+use audio::Audio;
+
+mod weapon;
+use weapon::{Beam, Delivery, WEAPONS};
+
 // Now this main module is just for the run-loop and rules processing.
 struct GameState {
     terrains: Vec<Entity<Terrain>>,
     mobiles: Vec<Entity<Mobile>>,
     projs: Vec<Projectile>,
     walls: Vec<Wall>,
+    spawns: Vec<Vec2i>,
+    theme: Theme,
     stage: GameStage,
     frame_count: usize,
-    scroll: Vec2i,
+    camera: Camera,
     score: usize,
     loaded: bool,
+    // Direction the player's turret points, in radians clockwise from the +x axis, in [0, 2π)
     aim: f64,
+    // Where the mouse is on screen, in screen pixels, if it has been over the window
+    cursor: Option<Vec2f>,
+    // Whether the turret follows the mouse; moving the mouse turns this on and aiming with the
+    // keys turns it off
+    mouse_aim: bool,
+    // Index into WEAPONS of the weapon the player has picked
+    weapon: usize,
+    // Frame the player started charging a shot, while they hold the fire key
+    charging: Option<usize>,
+    // Laser shots still fading out
+    beams: Vec<Beam>,
+    // Goes up every time terrain is damaged or destroyed, so anything worked out from the terrain
+    // can tell when it's out of date
+    terrain_version: usize,
+    // The shot preview's path, kept until what it was traced for changes; see preview_shot
+    preview: Option<(PreviewKey, Vec<Vec2f>)>,
+    // For the game over screen
+    shots_fired: usize,
+    shots_hit: usize,
+    particles: Particles,
+    effects: EffectStyles,
 }
 
 // TODO: Change this game stage
@@ -55,14 +114,32 @@ struct GameState {
 enum GameStage {
     Player,
     AI,
+    // The player's tank is blowing up
+    Dying,
+    // Frame at which the death animation finished
     GameOver(usize),
 }
 
+// Everything the shot preview's path depends on (apart from tanks, which it's cut short at as it's
+// drawn)
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PreviewKey {
+    weapon: usize,
+    aim: f64,
+    charge: f64,
+    player: Rect,
+    length: f64,
+    terrain_version: usize,
+}
+
 // seconds per frame
 const DT: f64 = 1.0 / 60.0;
 
 const WIDTH: usize = 640;
 const HEIGHT: usize = 480;
+// size of generated levels; the camera scrolls around ones bigger than the screen
+const LEVEL_WIDTH: usize = 960;
+const LEVEL_HEIGHT: usize = 720;
 const DEPTH: usize = 4;
 const TILEMAP_HT: usize = 256;
 
@@ -72,19 +149,101 @@ const ROCK_SZ: usize = 16;
 // player shoots every PROJ_DT frames
 const PROJ_DT: usize = 6;
 
-fn init(tile_sheet: &Rc<Texture>, sprite_sheet: &Rc<Texture>) -> GameState {
+// the player reloads every RELOAD_TIME frames
+const RELOAD_TIME: usize = 120;
+
+// frames to linger on the wreck of the player's tank before the game over screen
+const GAME_OVER_DELAY: usize = 60;
+
+// the player's tank blows up in slow motion
+const DEATH_ANIM_SPEED: f64 = 0.5;
+
+// how solid the player's turret looks while reloading
+const RELOADING_OPACITY: f64 = 0.35;
+
+// the aim arc spans AIM_ARC_ANGLE radians, AIM_ARC_RADIUS pixels from the player's tank
+const AIM_ARC_ANGLE: f64 = 0.6;
+const AIM_ARC_RADIUS: f64 = 20.0;
+
+// damaged tanks give off a puff of smoke every SMOKE_INTERVAL frames
+const SMOKE_INTERVAL: usize = 8;
+
+// how hard the camera shakes (see camera.rs) when an enemy or the player is destroyed
+const KILL_SHAKE: f64 = 0.4;
+const DEATH_SHAKE: f64 = 0.9;
+
+// how loud the music and sound effects play, from 0 to 1
+const MUSIC_VOLUME: f32 = 0.35;
+const EFFECTS_VOLUME: f32 = 0.8;
+
+// radians the turret turns per frame while an aiming key is held, normally and with Shift (fine)
+// or Ctrl (coarse) held too
+const AIM_STEP: f64 = 0.1;
+const FINE_AIM_STEP: f64 = 0.02;
+const COARSE_AIM_STEP: f64 = 0.25;
+
+// frames the fire key is held to charge a shot fully
+const CHARGE_TIME: usize = 90;
+
+// the aiming preview is drawn as a dot every PREVIEW_DOT_SPACING pixels along the shot's path
+const PREVIEW_DOT_SPACING: f64 = 8.0;
+
+// lobbed shells are drawn up to 1 + LOB_SCALE times their size at the top of their arc
+const LOB_SCALE: f64 = 1.5;
+
+// Tanks are square, TANK_SZ pixels on a side
+const TANK_SZ: i32 = 24;
+
+fn init(tile_sheet: &SpriteSheet, sprite_sheet: &SpriteSheet, settings: &Settings) -> GameState {
+    // Every match gets a freshly generated maze unless the player picked the hand-made map
+    let mut rng = rand::thread_rng();
+    let (grid, theme): (LevelGrid, Theme) = if settings.random_maze {
+        let config = MazeConfig::new(
+            rng.gen(),
+            (LEVEL_WIDTH / level::TILE_SZ, LEVEL_HEIGHT / level::TILE_SZ),
+        );
+        (maze::generate(&config), Theme::random(&mut rng))
+    } else {
+        (
+            classic_grid(Vec2i(WIDTH as i32, HEIGHT as i32)),
+            Theme::by_name("snow").unwrap(),
+        )
+    };
+
+    let bounds = Rect {
+        x: 0,
+        y: 0,
+        w: (grid.dims.0 * level::TILE_SZ) as u16,
+        h: (grid.dims.1 * level::TILE_SZ) as u16,
+    };
+    let mut camera = Camera::new((WIDTH, HEIGHT), bounds);
+    camera.jump_to(Vec2f(grid.spawns[0].0 as f64, grid.spawns[0].1 as f64));
+
     // Initial game state
     GameState {
-        terrains: level_walls(&tile_sheet, 0, Vec2i(WIDTH as i32, HEIGHT as i32)),
-        mobiles: vec![player_entity(&sprite_sheet, 0)],
+        terrains: level_walls(&tile_sheet, 0, &grid, &theme).unwrap(),
+        mobiles: vec![player_entity(&sprite_sheet, 0, spawn_pos(grid.spawns[0]))],
         projs: vec![],
         walls: vec![],
+        spawns: grid.spawns,
+        theme,
         stage: GameStage::Player,
         frame_count: 0,
-        scroll: Vec2i(0, 0),
+        camera,
         score: 0,
         loaded: false,
         aim: 0.,
+        cursor: None,
+        mouse_aim: false,
+        weapon: 0,
+        charging: None,
+        beams: vec![],
+        terrain_version: 0,
+        preview: None,
+        shots_fired: 0,
+        shots_hit: 0,
+        particles: Particles::new(),
+        effects: effect_styles(sprite_sheet).unwrap(),
     }
 }
 
@@ -107,11 +266,24 @@ fn main() {
         Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
     };
 
-    let sprite_sheet = Rc::new(Texture::with_file(Path::new("content/jackal_sprites.png")));
-    let font_sheet = Rc::new(Texture::with_file(Path::new("content/monospace_font.png")));
-    let tile_sheet = Rc::new(Texture::with_file(Path::new("content/mario_tileset.png")));
-
-    let mut state = init(&tile_sheet, &sprite_sheet);
+    let sprite_sheet = SpriteSheet::with_file(Path::new("content/jackal_sprites.png"))
+        .and_then(|s| s.with_graph("tank", Path::new("content/tank.anim")))
+        .and_then(|s| s.with_graph("turret", Path::new("content/turret.anim")))
+        .and_then(|s| s.with_graph("flash", Path::new("content/flash.anim")))
+        .unwrap();
+    let font = Font::with_file(Path::new("content/monospace_font.png")).unwrap();
+    let tile_sheet = SpriteSheet::with_file(Path::new("content/mario_tileset.png")).unwrap();
+    // Plays through the sound card, or silently if there isn't one
+    let mut audio = Audio::new();
+    audio.load_dir(Path::new("content/sounds")).unwrap();
+    audio.play_music("music", MUSIC_VOLUME);
+
+    let mut settings = Settings::default();
+    let mut state = init(&tile_sheet, &sprite_sheet, &settings);
+    // The game runs only while no menu is open; the top menu is drawn over it and gets the keys
+    let mut menus = vec![Menu::title()];
+    // Zoomed out views are drawn here before being shrunk onto the screen
+    let mut world_buf: Vec<u8> = vec![];
 
     // How many unsimulated frames have we saved up?
     let mut available_time = 0.0;
@@ -120,9 +292,31 @@ fn main() {
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            let mut screen = Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, state.scroll);
-            // Draw current game
-            draw_game(&mut state, &mut screen, &font_sheet);
+            let view = state.camera.view();
+            let view_pos = Vec2i(view.x, view.y);
+            if state.camera.zoom() == 1.0 {
+                let mut screen = Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, view_pos);
+                // Draw current game
+                draw_game(&mut state, &mut screen, &settings, &sprite_sheet);
+                let mut ui = screen.screen_space();
+                draw_hud(&state, &mut ui, &font);
+                if let Some(menu) = menus.last() {
+                    menu.draw(&mut ui, &font, &settings);
+                }
+            } else {
+                // Draw the game at full size off screen, then shrink it to fit the window
+                let (w, h) = (view.w as usize, view.h as usize);
+                world_buf.resize(w * h * DEPTH, 0);
+                let mut world = Screen::wrap(&mut world_buf, w, h, DEPTH, view_pos);
+                draw_game(&mut state, &mut world, &settings, &sprite_sheet);
+                let mut screen =
+                    Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, Vec2i(0, 0));
+                screen.stretch_from(&world);
+                draw_hud(&state, &mut screen, &font);
+                if let Some(menu) = menus.last() {
+                    menu.draw(&mut screen, &font, &settings);
+                }
+            }
             // Flip buffers
             if pixels.render().is_err() {
                 *control_flow = ControlFlow::Exit;
@@ -130,31 +324,78 @@ fn main() {
             }
             // Rendering has used up some time.
             // The renderer "produces" time...
-            available_time += since.elapsed().as_secs_f64();
+            let elapsed = since.elapsed().as_secs_f64();
+            available_time += elapsed;
+            audio.update(elapsed);
         }
         // Game over event
         if let GameStage::GameOver(death_frame) = state.stage {
-            if state.frame_count - death_frame >= 150 {
-                state = init(&tile_sheet, &sprite_sheet);
+            if menus.is_empty() && state.frame_count - death_frame >= GAME_OVER_DELAY {
+                menus.push(Menu::game_over(game_stats(&state)));
             }
         }
         // Handle input events
         if input.update(event) {
             // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            if input.quit() {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
+            // Escape pauses rather than quitting; quitting is on the menus
+            if let Some(menu) = menus.last_mut() {
+                match menu.input(&input, &mut settings) {
+                    Some(MenuAction::NewGame) => {
+                        state = init(&tile_sheet, &sprite_sheet, &settings);
+                        menus.clear();
+                    }
+                    Some(MenuAction::Back) => {
+                        menus.pop();
+                    }
+                    Some(MenuAction::OpenSettings) => menus.push(Menu::settings()),
+                    Some(MenuAction::ToTitle) => {
+                        state = init(&tile_sheet, &sprite_sheet, &settings);
+                        menus = vec![Menu::title()];
+                    }
+                    Some(MenuAction::Quit) => {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    None => {}
+                }
+            } else if input.key_pressed(VirtualKeyCode::Escape) {
+                menus.push(Menu::pause());
+            }
             // Resize the window if needed
             if let Some(size) = input.window_resized() {
                 pixels.resize(size.width, size.height);
             }
+            // Keep track of the mouse in screen pixels; moving it hands aiming over to it
+            if let Some(pos) = input.mouse() {
+                let (x, y) = pixels
+                    .window_pos_to_pixel(pos)
+                    .unwrap_or_else(|outside| pixels.clamp_pixel_pos(outside));
+                state.cursor = Some(Vec2f(x as f64, y as f64));
+                if input.mouse_diff() != (0.0, 0.0) {
+                    state.mouse_aim = true;
+                }
+            }
+        }
+        // The game stands still while a menu is open
+        if !menus.is_empty() {
+            available_time = 0.0;
         }
         // And the simulation "consumes" it
         while available_time >= DT {
             // Eat up one frame worth of time
             available_time -= DT;
-            update_game(&mut state, &input, &sprite_sheet, &tile_sheet);
+            update_game(
+                &mut state,
+                &input,
+                &settings,
+                &audio,
+                &sprite_sheet,
+                &tile_sheet,
+            );
             // Increment the frame counter
             state.frame_count += 1;
         }
@@ -165,191 +406,316 @@ fn main() {
     });
 }
 
-fn draw_game(state: &mut GameState, screen: &mut Screen, font_sheet: &Rc<Texture>) {
+fn draw_game(
+    state: &mut GameState,
+    screen: &mut Screen,
+    settings: &Settings,
+    sprite_sheet: &SpriteSheet,
+) {
     // Call screen's drawing methods to render the game state
-    screen.clear(Rgba(255, 197, 255, 255));
+    screen.clear(state.theme.background);
+    let mut list = DrawList::new();
 
+    // Shells are stretched to fit their colliders, and lobbed ones grow as they rise
+    let shell = sprite_sheet.atlas.frame("shell").unwrap();
     for proj in state.projs.iter() {
-        screen.rotated_rect(proj.rrect, Rgba(0, 0, 255, 255));
+        let r = proj.rrect;
+        let lift = 1.0 + proj.height() * LOB_SCALE;
+        list.push(
+            RenderLayer::Projectiles,
+            r.y as i32,
+            DrawCmd::Transformed {
+                texture: Rc::clone(&sprite_sheet.texture),
+                from: shell.rect,
+                to: Vec2f(r.x, r.y),
+                xform: Transform {
+                    pivot: Vec2f(shell.pivot.0 as f64, shell.pivot.1 as f64),
+                    rotation: r.rotation,
+                    scale: Vec2f(
+                        r.w as f64 / shell.rect.w as f64 * lift,
+                        r.h as f64 / shell.rect.h as f64 * lift,
+                    ),
+                },
+                sampling: Sampling::Bilinear,
+                style: BlitStyle::PLAIN,
+            },
+        );
     }
 
+    // Tanks further down the screen are in front
     for e in state.mobiles.iter_mut() {
-        screen.draw_sprite(&mut e.sprite, state.frame_count);
+        let bottom = e.collider.rect.y + e.collider.rect.h as i32;
+        list.sprite(RenderLayer::Units, bottom, &mut e.sprite, state.frame_count);
     }
 
     for e in state.terrains.iter_mut() {
-        screen.draw_sprite(&mut e.sprite, state.frame_count);
+        list.sprite(RenderLayer::Terrain, 0, &mut e.sprite, state.frame_count);
+    }
+
+    state.particles.draw(&mut list);
+    for beam in state.beams.iter() {
+        beam.draw(&mut list, state.frame_count);
     }
+    hud::hp_bars(&mut list, &state.mobiles);
 
-    // Draw aiming direction
+    state
+        .theme
+        .draw_ambient(&mut list, screen.bounds(), state.frame_count);
+
+    // Show which way the player is aiming with an arc just outside the tank
     if state.stage == GameStage::Player {
-        let (a, b) = (
-            state.mobiles[0].position.0 + state.mobiles[0].collider.rect.w as i32 / 2,
-            state.mobiles[0].position.1 + state.mobiles[0].collider.rect.h as i32 / 2,
-        );
-        let aimed_position = Vec2i(
-            (a as f64 + state.aim.cos() * 30.) as i32,
-            (b as f64 + state.aim.sin() * 30.) as i32,
+        let r = state.mobiles[0].collider.rect;
+        let (cx, cy) = (r.x as f64 + r.w as f64 / 2.0, r.y as f64 + r.h as f64 / 2.0);
+        let arc: Vec<Vec2f> = (0..=8)
+            .map(|i| {
+                let a = state.aim + (i as f64 / 8.0 - 0.5) * AIM_ARC_ANGLE;
+                Vec2f(cx + a.cos() * AIM_ARC_RADIUS, cy + a.sin() * AIM_ARC_RADIUS)
+            })
+            .collect();
+        list.push(
+            RenderLayer::Overlay,
+            0,
+            DrawCmd::Polyline(arc, 2.0, Rgba(255, 255, 255, 160)),
         );
-        screen.line(Vec2i(a, b), aimed_position, Rgba(0, 255, 0, 255));
-        screen.line(Vec2i(a - 1, b), aimed_position, Rgba(0, 255, 0, 255));
-        screen.line(Vec2i(a + 1, b), aimed_position, Rgba(0, 255, 0, 255));
+        preview_shot(state, settings, &mut list);
     }
 
-    // Draw HP bar
-    // draw_string("HP", screen, font_sheet, Vec2i(20, 520), state.scroll);
-    // let hp = state.mobiles[0].collider.hp;
-    // screen.rect(
-    //     Rect {
-    //         x: 70,
-    //         y: state.scroll.1 + 520,
-    //         w: hp as u16 * 2,
-    //         h: 18,
-    //     },
-    //     Rgba(0, 128, 0, 255),
-    // );
-    // screen.rect(
-    //     Rect {
-    //         x: 70 + (hp as i32 * 2),
-    //         y: state.scroll.1 + 520,
-    //         w: (100 - hp as u16) * 2,
-    //         h: 18,
-    //     },
-    //     Rgba(128, 0, 0, 255),
-    // );
-    // screen.line(
-    //     Vec2i(70, state.scroll.1 + 520),
-    //     Vec2i(270, state.scroll.1 + 520),
-    //     Rgba(0, 0, 0, 255),
-    // );
-    // screen.line(
-    //     Vec2i(270, state.scroll.1 + 520),
-    //     Vec2i(270, state.scroll.1 + 538),
-    //     Rgba(0, 0, 0, 255),
-    // );
-    // screen.line(
-    //     Vec2i(70, state.scroll.1 + 520),
-    //     Vec2i(70, state.scroll.1 + 538),
-    //     Rgba(0, 0, 0, 255),
-    // );
-    // screen.line(
-    //     Vec2i(70, state.scroll.1 + 538),
-    //     Vec2i(270, state.scroll.1 + 538),
-    //     Rgba(0, 0, 0, 255),
-    // );
-    // screen.line(
-    //     Vec2i(70 + (hp as i32 * 2), state.scroll.1 + 520),
-    //     Vec2i(70 + (hp as i32 * 2), state.scroll.1 + 538),
-    //     Rgba(0, 0, 0, 255),
-    // );
-
-    // Draw score
-    let mut score_msg = "Score ".to_string();
-    score_msg.push_str(&state.score.to_string());
-    draw_string(&score_msg, screen, font_sheet, Vec2i(20, 20), state.scroll);
-
-    // Draw game over message if game is over
-    if let GameStage::GameOver(_) = state.stage {
-        draw_string(
-            "Game over",
-            screen,
-            font_sheet,
-            Vec2i(80, 200),
-            state.scroll,
-        );
-        draw_string(
-            "Restarting",
-            screen,
-            font_sheet,
-            Vec2i(80, 250),
-            state.scroll,
-        );
+    list.render(screen);
+}
+
+// Dots along where the player's shot would go if fired now (the middle shell, for weapons that
+// fire several), fading out towards the end of as much of the path as the difficulty allows.
+// Tracing the path is slow, so it's only done again when something it depends on changes
+fn preview_shot(state: &mut GameState, settings: &Settings, list: &mut DrawList) {
+    let weapon = &WEAPONS[state.weapon];
+    let player = &state.mobiles[0].collider;
+    let length = settings.difficulty.preview_length();
+    let key = PreviewKey {
+        weapon: state.weapon,
+        aim: state.aim,
+        charge: charge(state),
+        player: player.rect,
+        length,
+        terrain_version: state.terrain_version,
+    };
+    if state.preview.as_ref().map(|(k, _)| *k) != Some(key) {
+        let path = if let Delivery::Beam { range } = weapon.delivery {
+            let from = collision::muzzle(player, state.aim);
+            collision::beam_path(&state.terrains, from, state.aim, range.min(length))
+        } else {
+            let shot = Projectile::new(player, state.aim, weapon, key.charge);
+            collision::trace_path(&state.terrains, &shot, length)
+        };
+        state.preview = Some((key, path));
+    }
+    let path = match &state.preview {
+        Some((_, path)) => path.as_slice(),
+        None => return,
+    };
+    // Beams go straight through tanks and lobbed shells fly over them; other shells stop at them
+    let path = match weapon.delivery {
+        Delivery::Shell => &path[..collision::path_to_mobile(path, &state.mobiles, weapon.size.0)],
+        _ => path,
+    };
+
+    // Walk the path, dropping a dot every PREVIEW_DOT_SPACING pixels
+    let mut walked = 0.0;
+    let mut next_dot = 0.0;
+    for pair in path.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let step = (b.0 - a.0).hypot(b.1 - a.1);
+        while next_dot <= walked + step {
+            let t = if step > 0.0 {
+                (next_dot - walked) / step
+            } else {
+                0.0
+            };
+            let at = Vec2f(a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            let alpha = 220.0 * (1.0 - next_dot / length).max(0.0);
+            list.push(
+                RenderLayer::Overlay,
+                0,
+                DrawCmd::Circle(at, 1.5, Rgba(255, 255, 255, alpha as u8)),
+            );
+            next_dot += PREVIEW_DOT_SPACING;
+        }
+        walked += step;
     }
 }
 
+/// How far the player has charged their shot, from 0 to 1; 0 if they aren't charging
+fn charge(state: &GameState) -> f64 {
+    match state.charging {
+        Some(start) => ((state.frame_count - start) as f64 / CHARGE_TIME as f64).min(1.0),
+        None => 0.0,
+    }
+}
+
+/// Draws the HUD over the game, in screen coordinates
+fn draw_hud(state: &GameState, screen: &mut Screen, font: &Font) {
+    let player = &state.mobiles[0].collider;
+    hud::draw(
+        screen,
+        font,
+        &HudState {
+            score: state.score,
+            hp: player.hp,
+            max_hp: player.max_hp,
+            fuel: player.fuel,
+            max_fuel: player.max_fuel,
+            turn: match state.stage {
+                GameStage::Player => Some("Your turn"),
+                GameStage::AI => Some("Enemy turn"),
+                _ => None,
+            },
+            weapon: WEAPONS[state.weapon].name,
+            charge: state.charging.map(|_| charge(state)),
+            loaded: state.loaded,
+            reload: ((state.frame_count + RELOAD_TIME / 2 + 1) % RELOAD_TIME) as f64
+                / RELOAD_TIME as f64,
+        },
+    );
+}
+
 fn update_game(
     state: &mut GameState,
     input: &WinitInputHelper,
-    sprite_sheet: &Rc<Texture>,
-    tile_sheet: &Rc<Texture>,
+    settings: &Settings,
+    audio: &Audio,
+    sprite_sheet: &SpriteSheet,
+    tile_sheet: &SpriteSheet,
 ) {
     // All time-based updating goes here:
-    if state.frame_count % 120 == 59 {
+    if state.frame_count % RELOAD_TIME == RELOAD_TIME / 2 - 1 {
         // let angle = (state.frame_count % 720) as f64 * 2.0 * std::f64::consts::PI / 720.0;
         state.loaded = true;
     }
-    if state.frame_count % 240 == 0 || state.mobiles.len() == 1 {
+    if state.frame_count % settings.difficulty.spawn_interval() == 0 || state.mobiles.len() == 1 {
+        // Enemies appear at one of the level's spawn points, never the player's
         let mut rng = rand::thread_rng();
-        let pos_x = rng.gen_range(0..WIDTH) as i32;
-        let pos_y = rng.gen_range(0..HEIGHT) as i32;
+        let first = if state.spawns.len() > 1 { 1 } else { 0 };
+        let spawn = state.spawns[rng.gen_range(first..state.spawns.len())];
 
         state.mobiles.push(enemy_entity(
             sprite_sheet,
             state.frame_count,
-            Vec2i(pos_x, pos_y),
+            spawn_pos(spawn),
         ));
     }
 
+    // Everything shots do to the game this frame; see ContactEvent
+    let mut events: Vec<ContactEvent> = vec![];
+
     match state.stage {
         // Update player position: Player control goes here
-        // Occupied keys: A, D, Up, Down, Left, Right, Space
+        // Occupied keys: A, D, Q, E, Up, Down, Left, Right, Space, Z, Shift, Ctrl; and the mouse
         GameStage::Player => {
-            // This block modifies player position:
-            // Nested if statements are used to ensure animation transitions are correct
-            if input.key_held(VirtualKeyCode::Right) {
-                if (state.mobiles[0].collider.vx - 1.0).abs() > 0.0 {
-                    state.mobiles[0].anim_trans("stop", state.frame_count);
-                    state.mobiles[0].anim_trans("right", state.frame_count);
-                    state.mobiles[0].anim_trans("move", state.frame_count);
-                    state.mobiles[0].collider.vx = 3.0;
-                    state.mobiles[0].collider.vy = 0.0;
-                }
-            } else if input.key_held(VirtualKeyCode::Left) {
-                if (state.mobiles[0].collider.vx - -1.0).abs() > 0.0 {
-                    state.mobiles[0].anim_trans("stop", state.frame_count);
-                    state.mobiles[0].anim_trans("left", state.frame_count);
-                    state.mobiles[0].anim_trans("move", state.frame_count);
-                    state.mobiles[0].collider.vx = -3.0;
-                    state.mobiles[0].collider.vy = 0.0;
-                }
-            } else if input.key_held(VirtualKeyCode::Up) {
-                if (state.mobiles[0].collider.vy - -1.0).abs() > 0.0 {
-                    state.mobiles[0].anim_trans("stop", state.frame_count);
-                    state.mobiles[0].anim_trans("up", state.frame_count);
-                    state.mobiles[0].anim_trans("move", state.frame_count);
-                }
-                state.mobiles[0].collider.vx = 0.0;
-                state.mobiles[0].collider.vy = -3.0;
-            } else if input.key_held(VirtualKeyCode::Down) {
-                if (state.mobiles[0].collider.vy - 1.0).abs() > 0.0 {
-                    state.mobiles[0].anim_trans("stop", state.frame_count);
-                    state.mobiles[0].anim_trans("down", state.frame_count);
-                    state.mobiles[0].anim_trans("move", state.frame_count);
-                }
-                state.mobiles[0].collider.vx = 0.0;
-                state.mobiles[0].collider.vy = 3.0;
+            // This block drives the player's tank: the arrow keys steer it any of eight ways, and
+            // it speeds up and slows down rather than starting and stopping dead. Running out of
+            // fuel for the turn is the exception: the tank stops where it is (see
+            // Mobile::ration_fuel)
+            let key = |k| if input.key_held(k) { 1.0 } else { 0.0 };
+            let steer = if state.mobiles[0].collider.fuel > 0.0 {
+                Vec2f(
+                    key(VirtualKeyCode::Right) - key(VirtualKeyCode::Left),
+                    key(VirtualKeyCode::Down) - key(VirtualKeyCode::Up),
+                )
             } else {
-                state.mobiles[0].anim_trans("stop", state.frame_count);
-                state.mobiles[0].collider.vx = 0.0;
-                state.mobiles[0].collider.vy = 0.0;
+                Vec2f(0.0, 0.0)
+            };
+            let player = &mut state.mobiles[0];
+            let before = (player.collider.heading(), player.collider.is_moving());
+            player.collider.drive(steer);
+            player.collider.face_velocity();
+            // The sprite points whichever of its four ways is closest to where the tank's heading,
+            // only changing animation when that or whether it's moving changes
+            let after = (player.collider.heading(), player.collider.is_moving());
+            if after != before {
+                player.anim_trans("stop", state.frame_count);
+                if after.1 {
+                    player.anim_trans(after.0, state.frame_count);
+                    player.anim_trans("move", state.frame_count);
+                }
             }
 
-            // This block aims the projectile:
+            // This block aims the turret: with A and D (Shift to turn it finely, Ctrl coarsely),
+            // or at the mouse once it moves
+            let step = if input.held_shift() {
+                FINE_AIM_STEP
+            } else if input.held_control() {
+                COARSE_AIM_STEP
+            } else {
+                AIM_STEP
+            };
             if input.key_held(VirtualKeyCode::A) {
-                state.aim -= 0.1;
+                state.mouse_aim = false;
+                state.aim -= step;
             } else if input.key_held(VirtualKeyCode::D) {
-                state.aim += 0.1;
+                state.mouse_aim = false;
+                state.aim += step;
+            } else if let (true, Some(cursor)) = (state.mouse_aim, state.cursor) {
+                let target = state.camera.to_world(cursor);
+                let r = state.mobiles[0].collider.rect;
+                let centre = Vec2f(r.x as f64 + r.w as f64 / 2.0, r.y as f64 + r.h as f64 / 2.0);
+                state.aim = (target.1 - centre.1).atan2(target.0 - centre.0);
+            }
+            state.aim = state.mobiles[0].collider.clamp_aim(state.aim);
+
+            // This block picks a weapon
+            if input.key_pressed(VirtualKeyCode::Q) {
+                state.weapon = (state.weapon + WEAPONS.len() - 1) % WEAPONS.len();
+            } else if input.key_pressed(VirtualKeyCode::E) {
+                state.weapon = (state.weapon + 1) % WEAPONS.len();
             }
 
-            // This block shoots
-            if input.key_pressed(VirtualKeyCode::Space) && state.loaded {
-                state
-                    .projs
-                    .push(Projectile::new(&state.mobiles[0].collider, state.aim));
+            // This block shoots: hold Space or the left mouse button to charge the shot, and let go
+            // to fire
+            let fire_pressed = input.key_pressed(VirtualKeyCode::Space) || input.mouse_pressed(0);
+            let fire_held = input.key_held(VirtualKeyCode::Space) || input.mouse_held(0);
+            if fire_pressed && state.loaded && state.charging.is_none() {
+                state.charging = Some(state.frame_count);
+            }
+            if state.charging.is_some() && !fire_held {
+                let charge = charge(state);
+                state.charging = None;
+                let weapon = &WEAPONS[state.weapon];
+                let player = &state.mobiles[0].collider;
+                let r = player.rect;
+                let pan = sound_pan(&state.camera, r.x as f64 + r.w as f64 / 2.0);
+                if let Delivery::Beam { .. } = weapon.delivery {
+                    let from = collision::muzzle(player, state.aim);
+                    let side = player.side();
+                    let to = collision::fire_beam(
+                        &mut state.terrains,
+                        &mut state.mobiles,
+                        from,
+                        state.aim,
+                        weapon,
+                        side,
+                        &mut events,
+                    );
+                    state.beams.push(Beam {
+                        from,
+                        to,
+                        fired_at: state.frame_count,
+                    });
+                    audio.play("laser", EFFECTS_VOLUME, pan);
+                } else {
+                    for angle in weapon.shell_angles(state.aim) {
+                        state
+                            .projs
+                            .push(Projectile::new(player, angle, weapon, charge));
+                    }
+                    audio.play("shot", EFFECTS_VOLUME, pan);
+                }
                 state.loaded = false;
+                state.shots_fired += 1;
+                // Firing ends the player's turn, so the tank is filled up for the next one
+                state.mobiles[0].collider.refuel();
             }
         }
         GameStage::AI => {}
+        GameStage::Dying => {}
         GameStage::GameOver(_) => {}
     }
 
@@ -369,20 +735,75 @@ fn update_game(
         &mut contacts,
     );
 
-    // Handle collisions
-    let (player_is_alive, scores_gained) = collision::handle_contact(
+    // Handle collisions, then let everything that cares react to what they and any laser shot did
+    collision::handle_contact(
         &mut state.terrains,
         &mut state.mobiles,
         &mut state.projs,
         &mut contacts,
+        &mut events,
     );
-    if !player_is_alive {
-        state.stage = GameStage::GameOver(state.frame_count);
+    for m in state.mobiles.iter_mut() {
+        m.collider.burn_fuel();
     }
+    for event in events.iter() {
+        show_contact(&mut state.particles, &state.effects, event);
+        sound_contact(audio, &state.camera, event);
+        match *event {
+            // Flash the player's tank when it takes damage
+            ContactEvent::MobileDamaged {
+                side: Side::Player, ..
+            } => state.mobiles[0].anim_trans("hit", state.frame_count),
+            ContactEvent::MobileDamaged {
+                side: Side::Enemy,
+                by: Side::Player,
+                ..
+            } => state.shots_hit += 1,
+            ContactEvent::TerrainDamaged { .. } | ContactEvent::TerrainDestroyed { .. } => {
+                state.terrain_version += 1
+            }
+            _ => {}
+        }
+    }
+    let player_is_alive = state.mobiles[0].collider.hp > 0;
+    let kills = events
+        .iter()
+        .filter(|e| {
+            matches!(
+                e,
+                ContactEvent::MobileKilled {
+                    side: Side::Enemy,
+                    ..
+                }
+            )
+        })
+        .count();
+
+    // Damaged tanks smoke, thicker the closer they are to blowing up
+    for m in state.mobiles.iter() {
+        let c = &m.collider;
+        if c.hp > 0 && c.hp < c.max_hp && state.frame_count.is_multiple_of(SMOKE_INTERVAL) {
+            let r = c.rect;
+            let puffs = if c.hp * 2 <= c.max_hp { 2 } else { 1 };
+            state.particles.burst(
+                &state.effects.smoke,
+                Vec2f(r.x as f64 + r.w as f64 / 2.0, r.y as f64 + r.h as f64 / 2.0),
+                puffs,
+                -std::f64::consts::FRAC_PI_2,
+                0.6,
+            );
+        }
+    }
+    state.particles.update();
+    let frame = state.frame_count;
+    state.beams.retain(|b| b.is_visible(frame));
 
-    // Update position of mobiles
+    // Update position of mobiles, as far as their fuel takes them
     for m in state.mobiles.iter_mut() {
-        m.move_pos(m.collider.vx as i32, m.collider.vy as i32);
+        m.collider.ration_fuel();
+        let (dx, dy) = m.collider.step();
+        m.move_pos(dx, dy);
+        m.collider.face_velocity();
     }
 
     // Update proj position
@@ -391,26 +812,143 @@ fn update_game(
         proj.update_pos();
     }
 
+    // The player's turret is the rocket it's about to fire, so it's faint until reloaded
+    if let Some(turret) = state.mobiles[0].sprite.layer_mut("turret") {
+        turret.style.opacity = if state.loaded { 1.0 } else { RELOADING_OPACITY };
+        turret.rotation = state.aim;
+    }
+
+    // Advance animations and act on the events they report
+    for (i, m) in state.mobiles.iter_mut().enumerate() {
+        m.sprite.update_anims(state.frame_count);
+        for event in m.sprite.drain_events() {
+            if i == 0 && event == "death_complete" {
+                state.stage = GameStage::GameOver(state.frame_count);
+            }
+        }
+    }
+
     if let GameStage::Player | GameStage::AI = state.stage {
-        // Set GameOver stage if player is not alive or if there are more than 15 enemy
+        // Blow the player up if they are not alive or if there are more than 10 enemies;
+        // the game is over once the death animation finishes
         if !player_is_alive || state.mobiles.len() >= 10 {
             state.mobiles[0]
                 .sprite
-                .animation_sm
-                .input("die", state.frame_count);
+                .set_speed(DEATH_ANIM_SPEED, state.frame_count);
+            state.mobiles[0].anim_trans("die", state.frame_count);
             state.mobiles[0].collider.vx = 0.0;
-            state.mobiles[0].collider.vy = -1.0;
-            state.stage = GameStage::GameOver(state.frame_count);
+            state.mobiles[0].collider.vy = 0.0;
+            state.stage = GameStage::Dying;
+            state.camera.shake(DEATH_SHAKE);
         } else {
-            state.score += scores_gained;
+            state.score += kills;
+            if kills > 0 {
+                state.camera.shake(KILL_SHAKE);
+            }
         }
     }
+
+    // Hold Z for an overview of the whole level; otherwise keep the player's tank in view
+    let zoom = if input.key_held(VirtualKeyCode::Z) {
+        state.camera.fit_zoom()
+    } else {
+        1.0
+    };
+    state.camera.set_zoom(zoom);
+    let r = state.mobiles[0].collider.rect;
+    state.camera.follow(Vec2f(
+        r.x as f64 + r.w as f64 / 2.0,
+        r.y as f64 + r.h as f64 / 2.0,
+    ));
+}
+
+/// Plays a sound for what a collision did, panned to where it happened
+fn sound_contact(audio: &Audio, camera: &Camera, event: &ContactEvent) {
+    let centre_x = |r: Rect| r.x as f64 + r.w as f64 / 2.0;
+    let (name, volume, x) = match *event {
+        ContactEvent::Bounce { at, .. } => ("bounce", 0.5, at.0),
+        ContactEvent::TerrainDamaged { .. } => return,
+        ContactEvent::TerrainDestroyed { rect } => ("explode", 0.5, centre_x(rect)),
+        ContactEvent::MobileDamaged { at, .. } => ("hit", 1.0, at.0),
+        ContactEvent::MobileKilled { rect, .. } => ("explode", 1.0, centre_x(rect)),
+        ContactEvent::Explosion { at, .. } => ("explode", 0.8, at.0),
+    };
+    audio.play(name, volume * EFFECTS_VOLUME, sound_pan(camera, x));
+}
+
+/// How far left (-1) or right (1) of the middle of the view a sound at world x should play
+fn sound_pan(camera: &Camera, x: f64) -> f32 {
+    let view = camera.view();
+    let half = view.w as f64 / 2.0;
+    ((x - view.x as f64 - half) / half).clamp(-1.0, 1.0) as f32
+}
+
+/// Throws out particles to show what a collision did
+fn show_contact(particles: &mut Particles, effects: &EffectStyles, event: &ContactEvent) {
+    let all_round = std::f64::consts::PI;
+    let centre = |r: Rect| Vec2f(r.x as f64 + r.w as f64 / 2.0, r.y as f64 + r.h as f64 / 2.0);
+    match *event {
+        // Sparks fly back off the face the shell hit
+        ContactEvent::Bounce { at, normal } => {
+            particles.burst(&effects.sparks, at, 6, normal.1.atan2(normal.0), 1.2)
+        }
+        ContactEvent::TerrainDamaged { rect, .. } => {
+            particles.burst(&effects.debris, centre(rect), 4, 0.0, all_round)
+        }
+        ContactEvent::TerrainDestroyed { rect } => {
+            particles.burst(&effects.debris, centre(rect), 16, 0.0, all_round)
+        }
+        ContactEvent::MobileDamaged { at, .. } => {
+            particles.burst(&effects.blast, at, 1, 0.0, 0.0);
+            particles.burst(&effects.sparks, at, 10, 0.0, all_round);
+        }
+        ContactEvent::MobileKilled { rect, .. } => {
+            particles.burst(&effects.debris, centre(rect), 20, 0.0, all_round)
+        }
+        // A ring of blasts around the middle one fills the burst out towards its radius
+        ContactEvent::Explosion { at, radius } => {
+            particles.burst(&effects.blast, at, 1, 0.0, 0.0);
+            for i in 0..6 {
+                let a = i as f64 * all_round / 3.0;
+                let around = Vec2f(at.0 + a.cos() * radius / 2.0, at.1 + a.sin() * radius / 2.0);
+                particles.burst(&effects.blast, around, 1, 0.0, 0.0);
+            }
+            particles.burst(&effects.sparks, at, 16, 0.0, all_round);
+            particles.burst(&effects.smoke, at, 6, 0.0, all_round);
+        }
+    }
+}
+
+/// The summary shown on the game over screen
+fn game_stats(state: &GameState) -> String {
+    let seconds = (state.frame_count as f64 * DT) as usize;
+    format!(
+        "Score: {}\nShots fired: {}\nHits: {}\nTime: {}:{:02}",
+        state.score,
+        state.shots_fired,
+        state.shots_hit,
+        seconds / 60,
+        seconds % 60
+    )
+}
+
+/// Top-left position for a tank centred on a level spawn point
+fn spawn_pos(spawn: Vec2i) -> Vec2i {
+    Vec2i(spawn.0 - TANK_SZ / 2, spawn.1 - TANK_SZ / 2)
 }
 
 fn update_enemies(state: &mut GameState) {
     let player_pos = state.mobiles[0].position.clone();
 
     for enemy in state.mobiles.iter_mut().skip(1) {
+        // Keep the turret trained on the player, as far as it can turn
+        let aim = ((player_pos.1 - enemy.position.1) as f64)
+            .atan2((player_pos.0 - enemy.position.0) as f64);
+        let aim = enemy.collider.clamp_aim(aim);
+        if let Some(turret) = enemy.sprite.layer_mut("turret") {
+            turret.rotation = aim;
+        }
+
         // Accelerate away from nearby terrain
         for terrain in state.terrains.iter() {
             let dx = (terrain.position.0 - enemy.position.0) as f32;
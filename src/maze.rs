@@ -0,0 +1,255 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::level::{LevelGrid, TILE_SZ};
+use crate::types::Vec2i;
+
+/// Enemies don't spawn in cells fewer than this many passages from the player's
+const MIN_SPAWN_STEPS: usize = 3;
+
+/// Knobs for the maze generator
+#[derive(Clone, Copy, Debug)]
+pub struct MazeConfig {
+    /// Same seed, same maze
+    pub seed: u64,
+    /// Size of the map in tiles
+    pub dims: (usize, usize),
+    /// How many tiles wide each corridor is
+    pub corridor_width: usize,
+    /// How many tiles thick each wall is
+    pub wall_width: usize,
+    /// Fraction (0.0 to 1.0) of the maze's inner walls that can be shot away
+    pub destructible_ratio: f64,
+}
+
+impl MazeConfig {
+    pub fn new(seed: u64, dims: (usize, usize)) -> Self {
+        Self {
+            seed,
+            dims,
+            corridor_width: 3,
+            wall_width: 2,
+            destructible_ratio: 0.4,
+        }
+    }
+}
+
+/*
+The maze is a grid of square cells, each corridor_width tiles wide, separated by walls that are
+wall_width tiles thick. We carve it with a recursive backtracker (iteratively, with a stack), which
+gives a perfect maze: every cell can reach every other cell, so all spawn points are connected
+without shooting anything.
+
+The wall segments left between two neighbouring cells are the ones that may be destructible. The
+pillars at cell corners and the outer border always stay.
+*/
+pub fn generate(config: &MazeConfig) -> LevelGrid {
+    let (tw, th) = config.dims;
+    let corridor = config.corridor_width.max(1);
+    let wall = config.wall_width.max(1);
+    let pitch = corridor + wall;
+    assert!(
+        tw >= pitch + wall && th >= pitch + wall,
+        "Map is too small for a single maze cell"
+    );
+
+    let cols = (tw - wall) / pitch;
+    let rows = (th - wall) / pitch;
+    // Centre the maze in the map; any leftover tiles become extra border
+    let off_x = (tw - (cols * pitch + wall)) / 2;
+    let off_y = (th - (rows * pitch + wall)) / 2;
+
+    let ratio = config.destructible_ratio.clamp(0.0, 1.0);
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut solid = vec![true; tw * th];
+    let mut destructible = vec![false; tw * th];
+
+    // Top-left tile of a cell's open area
+    let cell_origin = |c: usize, r: usize| (off_x + wall + c * pitch, off_y + wall + r * pitch);
+
+    for r in 0..rows {
+        for c in 0..cols {
+            let (x, y) = cell_origin(c, r);
+            fill(&mut solid, tw, x, y, corridor, corridor, false);
+        }
+    }
+
+    // Carve passages between cells with a depth-first walk
+    let mut visited = vec![false; cols * rows];
+    // open_right[i] / open_down[i]: is there a passage from cell i to its right/lower neighbour?
+    let mut open_right = vec![false; cols * rows];
+    let mut open_down = vec![false; cols * rows];
+    let start = rng.gen_range(0..cols * rows);
+    let mut stack = vec![start];
+    visited[start] = true;
+    while let Some(&cell) = stack.last() {
+        let (c, r) = (cell % cols, cell / cols);
+        let mut neighbours = vec![];
+        if c > 0 && !visited[cell - 1] {
+            neighbours.push(cell - 1);
+        }
+        if c + 1 < cols && !visited[cell + 1] {
+            neighbours.push(cell + 1);
+        }
+        if r > 0 && !visited[cell - cols] {
+            neighbours.push(cell - cols);
+        }
+        if r + 1 < rows && !visited[cell + cols] {
+            neighbours.push(cell + cols);
+        }
+
+        if neighbours.is_empty() {
+            stack.pop();
+            continue;
+        }
+
+        let next = neighbours[rng.gen_range(0..neighbours.len())];
+        if next == cell + 1 {
+            open_right[cell] = true;
+        } else if next + 1 == cell {
+            open_right[next] = true;
+        } else if next == cell + cols {
+            open_down[cell] = true;
+        } else {
+            open_down[next] = true;
+        }
+        visited[next] = true;
+        stack.push(next);
+    }
+
+    for r in 0..rows {
+        for c in 0..cols {
+            let i = r * cols + c;
+            let (x, y) = cell_origin(c, r);
+            if c + 1 < cols {
+                // The wall segment to the right of this cell
+                if open_right[i] {
+                    fill(&mut solid, tw, x + corridor, y, wall, corridor, false);
+                } else if rng.gen_bool(ratio) {
+                    fill(&mut destructible, tw, x + corridor, y, wall, corridor, true);
+                }
+            }
+            if r + 1 < rows {
+                // The wall segment below this cell
+                if open_down[i] {
+                    fill(&mut solid, tw, x, y + corridor, corridor, wall, false);
+                } else if rng.gen_bool(ratio) {
+                    fill(&mut destructible, tw, x, y + corridor, corridor, wall, true);
+                }
+            }
+        }
+    }
+
+    // Spawn points sit in the middle of each cell. The player gets cell 0 (top-left); the rest are
+    // ordered from the furthest walk away to the nearest, and the ones right next to the player
+    // are dropped so enemies never appear on top of them.
+    let steps = walk_distances(cols, rows, &open_right, &open_down);
+    let mut cells: Vec<usize> = (1..cols * rows).collect();
+    cells.sort_by_key(|&i| std::cmp::Reverse(steps[i]));
+    let far = cells
+        .iter()
+        .filter(|&&i| steps[i] >= MIN_SPAWN_STEPS)
+        .count();
+    cells.truncate(far.max(1));
+    let spawns: Vec<Vec2i> = std::iter::once(0)
+        .chain(cells)
+        .map(|i| {
+            let (x, y) = cell_origin(i % cols, i / cols);
+            Vec2i(
+                (x * TILE_SZ + corridor * TILE_SZ / 2) as i32,
+                (y * TILE_SZ + corridor * TILE_SZ / 2) as i32,
+            )
+        })
+        .collect();
+
+    LevelGrid::from_solid(config.dims, &solid, destructible, spawns)
+}
+
+/// Number of passages walked from cell 0 to each cell
+fn walk_distances(cols: usize, rows: usize, open_right: &[bool], open_down: &[bool]) -> Vec<usize> {
+    let mut steps = vec![usize::MAX; cols * rows];
+    let mut queue = std::collections::VecDeque::new();
+    steps[0] = 0;
+    queue.push_back(0);
+    while let Some(cell) = queue.pop_front() {
+        let c = cell % cols;
+        let mut neighbours = vec![];
+        if open_right[cell] {
+            neighbours.push(cell + 1);
+        }
+        if c > 0 && open_right[cell - 1] {
+            neighbours.push(cell - 1);
+        }
+        if open_down[cell] {
+            neighbours.push(cell + cols);
+        }
+        if cell >= cols && open_down[cell - cols] {
+            neighbours.push(cell - cols);
+        }
+        for next in neighbours {
+            if steps[next] == usize::MAX {
+                steps[next] = steps[cell] + 1;
+                queue.push_back(next);
+            }
+        }
+    }
+    steps
+}
+
+/// Sets a rectangle of a row-major flag grid
+fn fill(flags: &mut [bool], stride: usize, x0: usize, y0: usize, w: usize, h: usize, value: bool) {
+    for y in y0..(y0 + h) {
+        for x in x0..(x0 + w) {
+            flags[y * stride + x] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::NO_TILE;
+
+    #[test]
+    fn every_spawn_is_reachable() {
+        for seed in 0..8 {
+            let grid = generate(&MazeConfig::new(seed, (40, 30)));
+            let (w, h) = grid.dims;
+            let tile = |p: Vec2i| p.1 as usize / TILE_SZ * w + p.0 as usize / TILE_SZ;
+
+            // Flood the open tiles from the player's spawn
+            let mut reached = vec![false; w * h];
+            let mut stack = vec![tile(grid.spawns[0])];
+            while let Some(i) = stack.pop() {
+                if reached[i] || grid.tile_ids[i] != NO_TILE {
+                    continue;
+                }
+                reached[i] = true;
+                let (x, y) = (i % w, i / w);
+                if x > 0 {
+                    stack.push(i - 1);
+                }
+                if x + 1 < w {
+                    stack.push(i + 1);
+                }
+                if y > 0 {
+                    stack.push(i - w);
+                }
+                if y + 1 < h {
+                    stack.push(i + w);
+                }
+            }
+
+            assert!(grid.spawns.len() > 1, "seed {}: no enemy spawns", seed);
+            for &spawn in &grid.spawns {
+                assert!(
+                    reached[tile(spawn)],
+                    "seed {}: {:?} is walled off",
+                    seed,
+                    spawn
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+use crate::font::{Align, Font, TextStyle};
+use crate::screen::Screen;
+use crate::types::{Rect, Rgba, Vec2i};
+
+const TEXT_COLOUR: Rgba = Rgba(255, 255, 255, 255);
+const SELECTED_COLOUR: Rgba = Rgba(255, 220, 60, 255);
+const HIGHLIGHT_COLOUR: Rgba = Rgba(255, 255, 255, 40);
+// Vertical space between menu items
+const ITEM_SPACING: i32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Frames between new enemies arriving
+    pub fn spawn_interval(self) -> usize {
+        match self {
+            Difficulty::Easy => 360,
+            Difficulty::Normal => 240,
+            Difficulty::Hard => 160,
+        }
+    }
+
+    /// Pixels of a shot's path the aiming preview shows
+    pub fn preview_length(self) -> f64 {
+        match self {
+            Difficulty::Easy => 600.0,
+            Difficulty::Normal => 240.0,
+            Difficulty::Hard => 80.0,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    // The next setting along, wrapping around
+    fn cycle(self, step: i32) -> Self {
+        let all = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+        let i = all.iter().position(|d| *d == self).unwrap() as i32;
+        all[(i + step).rem_euclid(all.len() as i32) as usize]
+    }
+}
+
+/// Choices the player makes on the settings screen
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub difficulty: Difficulty,
+    // Play a freshly generated maze rather than the hand-made map
+    pub random_maze: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            difficulty: Difficulty::Normal,
+            random_maze: true,
+        }
+    }
+}
+
+/// What picking a menu item asks the game to do
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuAction {
+    NewGame,
+    // Close this menu and go back to whatever is under it
+    Back,
+    OpenSettings,
+    ToTitle,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MenuKind {
+    Title,
+    Pause,
+    GameOver,
+    Settings,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Item {
+    Action(&'static str, MenuAction),
+    Difficulty,
+    Maze,
+}
+
+/*
+Menus are kept on a stack by the game: whichever is on top is drawn over the (frozen) game and gets
+the keyboard, and the game only runs while the stack is empty. Up and Down pick an item, Enter or
+Space chooses it, Left and Right change settings, and Escape backs out (except on the title menu,
+which has nothing to go back to).
+*/
+pub struct Menu {
+    kind: MenuKind,
+    // Extra text under the heading, e.g. the stats on the game over screen
+    body: String,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn title() -> Self {
+        Self::new(MenuKind::Title, String::new())
+    }
+
+    pub fn pause() -> Self {
+        Self::new(MenuKind::Pause, String::new())
+    }
+
+    pub fn game_over(stats: String) -> Self {
+        Self::new(MenuKind::GameOver, stats)
+    }
+
+    pub fn settings() -> Self {
+        Self::new(MenuKind::Settings, String::new())
+    }
+
+    fn new(kind: MenuKind, body: String) -> Self {
+        Self {
+            kind,
+            body,
+            selected: 0,
+        }
+    }
+
+    fn heading(&self) -> &'static str {
+        match self.kind {
+            MenuKind::Title => "Tank Hunter",
+            MenuKind::Pause => "Paused",
+            MenuKind::GameOver => "Game over",
+            MenuKind::Settings => "Settings",
+        }
+    }
+
+    fn items(&self) -> Vec<Item> {
+        match self.kind {
+            MenuKind::Title => vec![
+                Item::Action("Play", MenuAction::NewGame),
+                Item::Action("Settings", MenuAction::OpenSettings),
+                Item::Action("Quit", MenuAction::Quit),
+            ],
+            MenuKind::Pause => vec![
+                Item::Action("Resume", MenuAction::Back),
+                Item::Action("Settings", MenuAction::OpenSettings),
+                Item::Action("Quit to title", MenuAction::ToTitle),
+            ],
+            MenuKind::GameOver => vec![
+                Item::Action("Play again", MenuAction::NewGame),
+                Item::Action("Quit to title", MenuAction::ToTitle),
+            ],
+            MenuKind::Settings => vec![
+                Item::Difficulty,
+                Item::Maze,
+                Item::Action("Back", MenuAction::Back),
+            ],
+        }
+    }
+
+    fn label(item: Item, settings: &Settings) -> String {
+        match item {
+            Item::Action(label, _) => label.to_string(),
+            Item::Difficulty => format!("Difficulty: {}", settings.difficulty.name()),
+            Item::Maze => format!(
+                "Maze: {}",
+                if settings.random_maze {
+                    "Random"
+                } else {
+                    "Classic"
+                }
+            ),
+        }
+    }
+
+    /// Reacts to this frame's key presses, changing settings in place; returns what the game
+    /// should do, if anything
+    pub fn input(
+        &mut self,
+        input: &WinitInputHelper,
+        settings: &mut Settings,
+    ) -> Option<MenuAction> {
+        let items = self.items();
+        let count = items.len();
+        if input.key_pressed(VirtualKeyCode::Up) {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if input.key_pressed(VirtualKeyCode::Down) {
+            self.selected = (self.selected + 1) % count;
+        }
+        if input.key_pressed(VirtualKeyCode::Escape) && self.kind != MenuKind::Title {
+            return Some(match self.kind {
+                MenuKind::GameOver => MenuAction::ToTitle,
+                _ => MenuAction::Back,
+            });
+        }
+
+        let chosen =
+            input.key_pressed(VirtualKeyCode::Return) || input.key_pressed(VirtualKeyCode::Space);
+        let step = if input.key_pressed(VirtualKeyCode::Left) {
+            -1
+        } else if input.key_pressed(VirtualKeyCode::Right) || chosen {
+            1
+        } else {
+            return None;
+        };
+        match items[self.selected] {
+            Item::Action(_, action) if chosen => Some(action),
+            Item::Action(..) => None,
+            Item::Difficulty => {
+                settings.difficulty = settings.difficulty.cycle(step);
+                None
+            }
+            Item::Maze => {
+                settings.random_maze = !settings.random_maze;
+                None
+            }
+        }
+    }
+
+    /// Draws the menu over the whole screen, in screen coordinates
+    pub fn draw(&self, screen: &mut Screen, font: &Font, settings: &Settings) {
+        let (w, h) = screen.size();
+        let (w, h) = (w as i32, h as i32);
+        // The title menu hides the game behind it; the others just dim it
+        let veil = if self.kind == MenuKind::Title {
+            220
+        } else {
+            160
+        };
+        screen.rect(screen.bounds(), Rgba(0, 0, 0, veil));
+
+        let style = TextStyle {
+            align: Align::Centre,
+            colour: TEXT_COLOUR,
+            ..TextStyle::PLAIN
+        };
+        let line = font.line_height();
+        let items = self.items();
+        let body_height = if self.body.is_empty() {
+            0
+        } else {
+            font.measure(&self.body, None).1 + line
+        };
+        // Heading, a blank line, the body, then the items; all centred on screen
+        let total = line * 2 + body_height + items.len() as i32 * (line + ITEM_SPACING);
+        let mut y = (h - total) / 2;
+
+        font.draw(screen, self.heading(), Vec2i(w / 2, y), style);
+        y += line * 2;
+        if !self.body.is_empty() {
+            font.draw(screen, &self.body, Vec2i(w / 2, y), style);
+            y += body_height;
+        }
+        for (i, item) in items.into_iter().enumerate() {
+            let label = Self::label(item, settings);
+            let selected = i == self.selected;
+            if selected {
+                let width = font.width(&label) + ITEM_SPACING * 2;
+                screen.rect(
+                    Rect {
+                        x: w / 2 - width / 2,
+                        y: y - ITEM_SPACING / 2,
+                        w: width as u16,
+                        h: (line + ITEM_SPACING) as u16,
+                    },
+                    HIGHLIGHT_COLOUR,
+                );
+            }
+            font.draw(
+                screen,
+                &label,
+                Vec2i(w / 2, y),
+                TextStyle {
+                    colour: if selected {
+                        SELECTED_COLOUR
+                    } else {
+                        TEXT_COLOUR
+                    },
+                    ..style
+                },
+            );
+            y += line + ITEM_SPACING;
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::rc::Rc;
+
+use rand::Rng;
+
+use crate::render::{DrawCmd, DrawList, RenderLayer};
+use crate::screen::{BlitStyle, Sampling, Transform};
+use crate::texture::Texture;
+use crate::types::{Rect, Rgba, Vec2f};
+
+// Most particles alive at once; bursts past this are cut short
+const MAX_PARTICLES: usize = 2048;
+
+/// How one kind of particle moves and looks over its life
+pub struct ParticleStyle {
+    // Frames each particle lives for, picked at random between the two
+    pub life: (usize, usize),
+    // Pixels per frame each particle sets off at, picked at random between the two
+    pub speed: (f64, f64),
+    // Added to every particle's velocity each frame
+    pub gravity: Vec2f,
+    // Fraction of its velocity a particle loses each frame
+    pub drag: f64,
+    // Colours at birth and death; particles fade from one to the other, alpha included
+    pub colour: (Rgba, Rgba),
+    // Radius in pixels at birth and death
+    pub radius: (f64, f64),
+    // Sprite frames played once over a particle's life, drawn in place of a dot: scaled to the
+    // particle's size and tinted by its colour
+    pub frames: Option<(Rc<Texture>, Vec<Rect>)>,
+}
+
+struct Particle {
+    style: Rc<ParticleStyle>,
+    pos: Vec2f,
+    vel: Vec2f,
+    age: usize,
+    life: usize,
+}
+
+/*
+Particles are short-lived bits of decoration (sparks, smoke, debris) that don't collide with
+anything. They're kept in one Vec allocated up front and never grown, so thousands of them can come
+and go every second without allocating.
+*/
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Particles {
+    pub fn new() -> Self {
+        Self {
+            particles: Vec::with_capacity(MAX_PARTICLES),
+        }
+    }
+
+    /// Throws out count particles from at, each heading up to spread radians either side of
+    /// direction (radians clockwise from the +x axis)
+    pub fn burst(
+        &mut self,
+        style: &Rc<ParticleStyle>,
+        at: Vec2f,
+        count: usize,
+        direction: f64,
+        spread: f64,
+    ) {
+        let mut rng = rand::thread_rng();
+        let count = count.min(MAX_PARTICLES - self.particles.len());
+        for _ in 0..count {
+            let angle = direction + rng.gen_range(-spread..=spread);
+            let speed = rng.gen_range(style.speed.0..=style.speed.1);
+            self.particles.push(Particle {
+                style: Rc::clone(style),
+                pos: at,
+                vel: Vec2f(angle.cos() * speed, angle.sin() * speed),
+                age: 0,
+                life: rng.gen_range(style.life.0..=style.life.1).max(1),
+            });
+        }
+    }
+
+    /// Moves every particle on a frame and clears out the ones that have died
+    pub fn update(&mut self) {
+        for p in self.particles.iter_mut() {
+            let s = &p.style;
+            p.vel = Vec2f(
+                (p.vel.0 + s.gravity.0) * (1.0 - s.drag),
+                (p.vel.1 + s.gravity.1) * (1.0 - s.drag),
+            );
+            p.pos = Vec2f(p.pos.0 + p.vel.0, p.pos.1 + p.vel.1);
+            p.age += 1;
+        }
+        self.particles.retain(|p| p.age < p.life);
+    }
+
+    /// Submits every particle, in world coordinates
+    pub fn draw(&self, list: &mut DrawList) {
+        for p in self.particles.iter() {
+            let s = &p.style;
+            let t = p.age as f64 / p.life as f64;
+            let lerp = |a: f64, b: f64| a + (b - a) * t;
+            let channel = |a: u8, b: u8| lerp(a as f64, b as f64).round() as u8;
+            let (from, to) = s.colour;
+            let col = Rgba(
+                channel(from.0, to.0),
+                channel(from.1, to.1),
+                channel(from.2, to.2),
+                channel(from.3, to.3),
+            );
+            let radius = lerp(s.radius.0, s.radius.1);
+
+            let cmd = match &s.frames {
+                Some((texture, frames)) => {
+                    let frame = frames[((t * frames.len() as f64) as usize).min(frames.len() - 1)];
+                    DrawCmd::Transformed {
+                        texture: Rc::clone(texture),
+                        from: frame,
+                        to: p.pos,
+                        xform: Transform {
+                            pivot: Vec2f(frame.w as f64 / 2.0, frame.h as f64 / 2.0),
+                            rotation: 0.0,
+                            scale: Vec2f(
+                                radius * 2.0 / frame.w as f64,
+                                radius * 2.0 / frame.h as f64,
+                            ),
+                        },
+                        sampling: Sampling::Bilinear,
+                        style: BlitStyle {
+                            tint: col,
+                            ..BlitStyle::PLAIN
+                        },
+                    }
+                }
+                None => DrawCmd::Circle(p.pos, radius, col),
+            };
+            list.push(RenderLayer::Effects, 0, cmd);
+        }
+    }
+}
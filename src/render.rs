@@ -0,0 +1,97 @@
+use std::rc::Rc;
+
+use crate::screen::{BlitStyle, Sampling, Screen, Transform};
+use crate::sprite::Sprite;
+use crate::texture::Texture;
+use crate::types::{Rect, Rgba, Vec2f, Vec2i};
+
+/// Broad passes of a frame, drawn back to front
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum RenderLayer {
+    Terrain,
+    Units,
+    Projectiles,
+    // Sparks, smoke and other particles
+    Effects,
+    Weather,
+    // Aim arcs and the like, drawn over the world
+    Overlay,
+    #[allow(dead_code)]
+    Ui,
+}
+
+/// One thing to draw, in the same terms as the Screen method that draws it
+pub enum DrawCmd {
+    Blit {
+        texture: Rc<Texture>,
+        from: Rect,
+        to: Vec2i,
+        style: BlitStyle,
+    },
+    Transformed {
+        texture: Rc<Texture>,
+        from: Rect,
+        to: Vec2f,
+        xform: Transform,
+        sampling: Sampling,
+        style: BlitStyle,
+    },
+    Rect(Rect, Rgba),
+    // Centre and radius
+    Circle(Vec2f, f64, Rgba),
+    Polyline(Vec<Vec2f>, f64, Rgba),
+}
+
+/*
+Rather than drawing straight to the screen, draw_game submits everything to a DrawList along with
+a layer and a z value, and the list sorts it all out before drawing. Within a layer, lower z is
+drawn first; commands with the same layer and z are drawn in the order they were submitted.
+*/
+#[derive(Default)]
+pub struct DrawList {
+    cmds: Vec<(RenderLayer, i32, DrawCmd)>,
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, layer: RenderLayer, z: i32, cmd: DrawCmd) {
+        self.cmds.push((layer, z, cmd));
+    }
+
+    /// Submits every visible layer of s at its current frame
+    pub fn sprite(&mut self, layer: RenderLayer, z: i32, s: &mut Sprite, now: usize) {
+        for cmd in s.draw_cmds(now) {
+            self.push(layer, z, cmd);
+        }
+    }
+
+    /// Draws everything submitted so far in order and empties the list
+    pub fn render(&mut self, screen: &mut Screen) {
+        // sort_by_key is stable, so submission order breaks ties
+        self.cmds.sort_by_key(|(layer, z, _)| (*layer, *z));
+        for (_, _, cmd) in self.cmds.drain(..) {
+            match cmd {
+                DrawCmd::Blit {
+                    texture,
+                    from,
+                    to,
+                    style,
+                } => screen.bitblt_styled(&texture, from, to, style),
+                DrawCmd::Transformed {
+                    texture,
+                    from,
+                    to,
+                    xform,
+                    sampling,
+                    style,
+                } => screen.blit_transformed(&texture, from, to, xform, sampling, style),
+                DrawCmd::Rect(r, col) => screen.rect(r, col),
+                DrawCmd::Circle(centre, radius, col) => screen.fill_circle(centre, radius, col),
+                DrawCmd::Polyline(points, width, col) => screen.polyline(&points, width, col),
+            }
+        }
+    }
+}
@@ -1,6 +1,96 @@
 // We can pull in definitions from elsewhere in the crate!
 use crate::texture::Texture;
-use crate::types::{Rect, Rgba, RotatedRect, Vec2i};
+use crate::types::{Rect, Rgba, RotatedRect, Vec2f, Vec2i};
+
+/// How a transformed blit picks source colours for screen pixels
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sampling {
+    // Closest source pixel; keeps pixel art crisp
+    Nearest,
+    // Weighted average of the four closest source pixels; smoother when rotating or scaling
+    Bilinear,
+}
+
+/// Placement of a source region for Screen::blit_transformed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    // Point within the source region, from its top-left, that rotation and scaling happen about
+    pub pivot: Vec2f,
+    // Radians clockwise on screen, the same way aim angles go
+    pub rotation: f64,
+    pub scale: Vec2f,
+}
+
+/// Effects applied to the source image by Screen::bitblt_styled and Screen::blit_transformed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BlitStyle {
+    // Mirror the source region left-to-right and top-to-bottom
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Multiplies each colour channel; white leaves colours alone
+    pub tint: Rgba,
+    // 0.0 is invisible, 1.0 as opaque as the source
+    pub opacity: f64,
+}
+
+impl BlitStyle {
+    pub const PLAIN: BlitStyle = BlitStyle {
+        flip_x: false,
+        flip_y: false,
+        tint: Rgba(255, 255, 255, 255),
+        opacity: 1.0,
+    };
+
+    // Tints and fades a premultiplied colour
+    fn shade(&self, col: [f64; 4]) -> [f64; 4] {
+        let Rgba(r, g, b, a) = self.tint;
+        let fade = self.opacity * a as f64 / 255.0;
+        [
+            col[0] * r as f64 / 255.0 * fade,
+            col[1] * g as f64 / 255.0 * fade,
+            col[2] * b as f64 / 255.0 * fade,
+            col[3] * fade,
+        ]
+    }
+}
+
+impl Default for BlitStyle {
+    fn default() -> Self {
+        Self::PLAIN
+    }
+}
+
+// A straight-alpha colour premultiplied, with its alpha scaled by coverage
+fn premultiplied(col: Rgba, coverage: f64) -> [f64; 4] {
+    let a = col.3 as f64 * coverage;
+    [
+        col.0 as f64 * a / 255.0,
+        col.1 as f64 * a / 255.0,
+        col.2 as f64 * a / 255.0,
+        a,
+    ]
+}
+
+// Distance from p to the line segment from a to b
+fn segment_distance(p: Vec2f, a: Vec2f, b: Vec2f) -> f64 {
+    let ab = Vec2f(b.0 - a.0, b.1 - a.1);
+    let ap = Vec2f(p.0 - a.0, p.1 - a.1);
+    let len2 = ab.dot(&ab);
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (ap.dot(&ab) / len2).clamp(0.0, 1.0)
+    };
+    Vec2f(ap.0 - ab.0 * t, ap.1 - ab.1 * t).norm()
+}
+
+// Composite over, both sides premultiplied
+fn blend_over(to: &mut [u8], col: [f64; 4]) {
+    let keep = 1.0 - col[3] / 255.0;
+    for i in 0..4 {
+        to[i] = (col[i] + to[i] as f64 * keep).round().min(255.0) as u8;
+    }
+}
 
 pub struct Screen<'fb> {
     framebuffer: &'fb mut [u8],
@@ -27,6 +117,33 @@ impl<'fb> Screen<'fb> {
         }
     }
 
+    /// The same framebuffer, but drawing in screen coordinates rather than world coordinates
+    pub fn screen_space(&mut self) -> Screen<'_> {
+        Screen {
+            framebuffer: &mut *self.framebuffer,
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+            position: Vec2i(0, 0),
+        }
+    }
+
+    /// Fills the whole screen with src stretched to fit, e.g. to show a zoomed out view
+    pub fn stretch_from(&mut self, src: &Screen) {
+        assert_eq!(self.depth, src.depth);
+        let depth = self.depth;
+        for y in 0..self.height {
+            let sy = y * src.height / self.height;
+            for x in 0..self.width {
+                let sx = x * src.width / self.width;
+                let from = (sy * src.width + sx) * depth;
+                let to = (y * self.width + x) * depth;
+                self.framebuffer[to..to + depth]
+                    .copy_from_slice(&src.framebuffer[from..from + depth]);
+            }
+        }
+    }
+
     #[allow(dead_code)]
     pub fn size(&self) -> (usize, usize) {
         (self.width, self.height)
@@ -100,13 +217,17 @@ impl<'fb> Screen<'fb> {
         let pitch = self.width * depth;
         for row in self.framebuffer[(y0 * pitch)..(y1 * pitch)].chunks_exact_mut(pitch) {
             for p in row[(x0 * depth)..(x1 * depth)].chunks_exact_mut(depth) {
-                // TODO should handle alpha blending
-                p.copy_from_slice(&c);
+                if col.3 == 255 {
+                    p.copy_from_slice(&c);
+                } else {
+                    blend_over(p, premultiplied(col, 1.0));
+                }
             }
         }
     }
 
     // Draw RotatedRect
+    #[allow(dead_code)]
     pub fn rotated_rect(&mut self, r: RotatedRect, col: Rgba) {
         let corners = r.corners();
 
@@ -132,7 +253,134 @@ impl<'fb> Screen<'fb> {
         );
     }
 
+    /// Fills r, blending with what's underneath
+    #[allow(dead_code)]
+    pub fn fill_rotated_rect(&mut self, r: RotatedRect, col: Rgba) {
+        self.fill_polygon(&r.corners(), col);
+    }
+
+    /// Fills the polygon with the given corners, blending with what's underneath.
+    /// Pixels are filled if their middles are inside, so shapes sharing an edge don't overlap.
+    pub fn fill_polygon(&mut self, points: &[Vec2f], col: Rgba) {
+        if points.len() < 3 {
+            return;
+        }
+        let (px, py) = (self.position.0 as f64, self.position.1 as f64);
+        let pts: Vec<Vec2f> = points.iter().map(|p| Vec2f(p.0 - px, p.1 - py)).collect();
+        let min_y = pts.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max_y = pts.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        let y0 = (min_y.floor().max(0.0) as usize).min(self.height);
+        let y1 = (max_y.ceil().max(0.0) as usize).min(self.height);
+        let c = premultiplied(col, 1.0);
+
+        let mut crossings = vec![];
+        for y in y0..y1 {
+            // Scanline through the middle of the row; fill between pairs of edge crossings
+            let sy = y as f64 + 0.5;
+            crossings.clear();
+            for (i, a) in pts.iter().enumerate() {
+                let b = pts[(i + 1) % pts.len()];
+                if (a.1 <= sy) != (b.1 <= sy) {
+                    crossings.push(a.0 + (sy - a.1) / (b.1 - a.1) * (b.0 - a.0));
+                }
+            }
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for pair in crossings.chunks_exact(2) {
+                let x0 = ((pair[0] - 0.5).ceil().max(0.0) as usize).min(self.width);
+                let x1 = ((pair[1] - 0.5).ceil().max(0.0) as usize).min(self.width);
+                for x in x0..x1 {
+                    self.blend_at(x, y, c);
+                }
+            }
+        }
+    }
+
+    /// Fills a circle with a soft, anti-aliased edge
+    #[allow(dead_code)]
+    pub fn fill_circle(&mut self, centre: Vec2f, radius: f64, col: Rgba) {
+        self.shade_around(centre, radius + 1.0, col, |d| radius - d + 0.5);
+    }
+
+    /// Draws the outline of a circle, thickness pixels wide and anti-aliased
+    #[allow(dead_code)]
+    pub fn circle(&mut self, centre: Vec2f, radius: f64, thickness: f64, col: Rgba) {
+        self.shade_around(centre, radius + thickness, col, |d| {
+            thickness / 2.0 - (d - radius).abs() + 0.5
+        });
+    }
+
+    /// Draws an anti-aliased line width pixels wide, with rounded ends
+    #[allow(dead_code)]
+    pub fn thick_line(&mut self, a: Vec2f, b: Vec2f, width: f64, col: Rgba) {
+        self.polyline(&[a, b], width, col);
+    }
+
+    /// Draws anti-aliased lines width pixels wide joining up points.
+    /// Where segments overlap at the joins, pixels are only blended once.
+    pub fn polyline(&mut self, points: &[Vec2f], width: f64, col: Rgba) {
+        if points.is_empty() {
+            return;
+        }
+        let (px, py) = (self.position.0 as f64, self.position.1 as f64);
+        let pts: Vec<Vec2f> = points.iter().map(|p| Vec2f(p.0 - px, p.1 - py)).collect();
+        let reach = width / 2.0 + 1.0;
+        let x0 = pts.iter().map(|p| p.0).fold(f64::INFINITY, f64::min) - reach;
+        let x1 = pts.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max) + reach;
+        let y0 = pts.iter().map(|p| p.1).fold(f64::INFINITY, f64::min) - reach;
+        let y1 = pts.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max) + reach;
+
+        for y in self.clamp_span(y0, y1, self.height) {
+            for x in self.clamp_span(x0, x1, self.width) {
+                let p = Vec2f(x as f64 + 0.5, y as f64 + 0.5);
+                let d = if pts.len() == 1 {
+                    Vec2f(p.0 - pts[0].0, p.1 - pts[0].1).norm()
+                } else {
+                    pts.windows(2)
+                        .map(|s| segment_distance(p, s[0], s[1]))
+                        .fold(f64::INFINITY, f64::min)
+                };
+                let coverage = (width / 2.0 - d + 0.5).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    self.blend_at(x, y, premultiplied(col, coverage));
+                }
+            }
+        }
+    }
+
+    // Blends col into every pixel within reach of centre, weighted by coverage(distance to centre)
+    fn shade_around(
+        &mut self,
+        centre: Vec2f,
+        reach: f64,
+        col: Rgba,
+        coverage: impl Fn(f64) -> f64,
+    ) {
+        let cx = centre.0 - self.position.0 as f64;
+        let cy = centre.1 - self.position.1 as f64;
+        for y in self.clamp_span(cy - reach, cy + reach, self.height) {
+            for x in self.clamp_span(cx - reach, cx + reach, self.width) {
+                let d = Vec2f(x as f64 + 0.5 - cx, y as f64 + 0.5 - cy).norm();
+                let c = coverage(d).clamp(0.0, 1.0);
+                if c > 0.0 {
+                    self.blend_at(x, y, premultiplied(col, c));
+                }
+            }
+        }
+    }
+
+    // Pixel indices from lo to hi, kept inside 0..len
+    fn clamp_span(&self, lo: f64, hi: f64, len: usize) -> std::ops::Range<usize> {
+        (lo.floor().max(0.0) as usize).min(len)..(hi.ceil().max(0.0) as usize).min(len)
+    }
+
+    // Blends a premultiplied colour into the pixel at screen (not world) coordinates x, y
+    fn blend_at(&mut self, x: usize, y: usize, col: [f64; 4]) {
+        let idx = y * self.width * self.depth + x * self.depth;
+        blend_over(&mut self.framebuffer[idx..idx + self.depth], col);
+    }
+
     // Ditto line
+    #[allow(dead_code)]
     pub fn line(&mut self, Vec2i(x0, y0): Vec2i, Vec2i(x1, y1): Vec2i, col: Rgba) {
         let col = [col.0, col.1, col.2, col.3];
         // translate translate
@@ -160,10 +408,13 @@ impl<'fb> Screen<'fb> {
             if 0 <= x && x < width && 0 <= y && y < height {
                 // TODO this bounds check could in theory be avoided with
                 // the unsafe get_unchecked, but maybe better not...
-                // TODO better handle alpha blending too, but not just yet...
-                self.framebuffer[(y as usize * pitch + x as usize * depth)
-                    ..(y as usize * pitch + (x as usize + 1) * depth)]
-                    .copy_from_slice(&col);
+                let p = &mut self.framebuffer[(y as usize * pitch + x as usize * depth)
+                    ..(y as usize * pitch + (x as usize + 1) * depth)];
+                if col[3] == 255 {
+                    p.copy_from_slice(&col);
+                } else {
+                    blend_over(p, premultiplied(Rgba(col[0], col[1], col[2], col[3]), 1.0));
+                }
             }
             let e2 = 2 * err;
             if dy <= e2 {
@@ -231,4 +482,160 @@ impl<'fb> Screen<'fb> {
             }
         }
     }
+
+    /// Draws the from region of src rotated and scaled about xform.pivot, which lands on to.
+    /// Works backwards from each screen pixel to the source point that maps onto it.
+    pub fn blit_transformed(
+        &mut self,
+        src: &Texture,
+        from: Rect,
+        to: Vec2f,
+        xform: Transform,
+        sampling: Sampling,
+        style: BlitStyle,
+    ) {
+        assert!(src.valid_frame(from));
+        assert_eq!(self.depth, src.depth());
+        if xform.scale.0 == 0.0 || xform.scale.1 == 0.0 {
+            return;
+        }
+        let (sin, cos) = xform.rotation.sin_cos();
+        // Screen position (relative to to) of a point within the source region
+        let forward = |u: f64, v: f64| {
+            let x = (u - xform.pivot.0) * xform.scale.0;
+            let y = (v - xform.pivot.1) * xform.scale.1;
+            (x * cos - y * sin, x * sin + y * cos)
+        };
+
+        // Only visit screen pixels inside the transformed region's bounding box
+        let (w, h) = (from.w as f64, from.h as f64);
+        let corners = [
+            forward(0.0, 0.0),
+            forward(w, 0.0),
+            forward(w, h),
+            forward(0.0, h),
+        ];
+        let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+        let max_x = corners
+            .iter()
+            .map(|c| c.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+        let max_y = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let ox = to.0 - self.position.0 as f64;
+        let oy = to.1 - self.position.1 as f64;
+        let x0 = ((ox + min_x).floor().max(0.0) as usize).min(self.width);
+        let x1 = ((ox + max_x).ceil().max(0.0) as usize).min(self.width);
+        let y0 = ((oy + min_y).floor().max(0.0) as usize).min(self.height);
+        let y1 = ((oy + max_y).ceil().max(0.0) as usize).min(self.height);
+
+        let depth = self.depth;
+        let pitch = self.width * depth;
+        let src_buf = src.buffer();
+        let src_pitch = src.pitch();
+        // Premultiplied colour of a source region pixel; transparent outside the region
+        let texel = |x: i32, y: i32| -> [f64; 4] {
+            if x < 0 || y < 0 || x >= from.w as i32 || y >= from.h as i32 {
+                return [0.0; 4];
+            }
+            let x = if style.flip_x {
+                from.w as i32 - 1 - x
+            } else {
+                x
+            };
+            let y = if style.flip_y {
+                from.h as i32 - 1 - y
+            } else {
+                y
+            };
+            let idx = (from.y + y) as usize * src_pitch + (from.x + x) as usize * depth;
+            let px = &src_buf[idx..idx + 4];
+            [px[0] as f64, px[1] as f64, px[2] as f64, px[3] as f64]
+        };
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // Undo the rotation, then the scale, from the middle of the screen pixel
+                let dx = x as f64 + 0.5 - ox;
+                let dy = y as f64 + 0.5 - oy;
+                let u = (dx * cos + dy * sin) / xform.scale.0 + xform.pivot.0;
+                let v = (-dx * sin + dy * cos) / xform.scale.1 + xform.pivot.1;
+
+                let col = match sampling {
+                    Sampling::Nearest => texel(u.floor() as i32, v.floor() as i32),
+                    Sampling::Bilinear => {
+                        let (u, v) = (u - 0.5, v - 0.5);
+                        let (fx, fy) = (u - u.floor(), v - v.floor());
+                        let (sx, sy) = (u.floor() as i32, v.floor() as i32);
+                        let (a, b) = (texel(sx, sy), texel(sx + 1, sy));
+                        let (c, d) = (texel(sx, sy + 1), texel(sx + 1, sy + 1));
+                        let mut col = [0.0; 4];
+                        for i in 0..4 {
+                            let top = a[i] + (b[i] - a[i]) * fx;
+                            let bottom = c[i] + (d[i] - c[i]) * fx;
+                            col[i] = top + (bottom - top) * fy;
+                        }
+                        col
+                    }
+                };
+                if col[3] <= 0.0 {
+                    continue;
+                }
+
+                blend_over(
+                    &mut self.framebuffer[y * pitch + x * depth..y * pitch + (x + 1) * depth],
+                    style.shade(col),
+                );
+            }
+        }
+    }
+
+    /// Like bitblt, but flipped, tinted and faded as style says
+    pub fn bitblt_styled(&mut self, src: &Texture, from: Rect, to: Vec2i, style: BlitStyle) {
+        if style == BlitStyle::PLAIN {
+            return self.bitblt(src, from, to);
+        }
+        assert!(src.valid_frame(from));
+        assert_eq!(self.depth, src.depth());
+        let to_x = to.0 - self.position.0;
+        let to_y = to.1 - self.position.1;
+        // Visible part of the destination, in screen pixels
+        let x0 = to_x.max(0);
+        let x1 = (to_x + from.w as i32).min(self.width as i32);
+        let y0 = to_y.max(0);
+        let y1 = (to_y + from.h as i32).min(self.height as i32);
+
+        let depth = self.depth;
+        let pitch = self.width * depth;
+        let src_buf = src.buffer();
+        let src_pitch = src.pitch();
+        for y in y0..y1 {
+            let sy = if style.flip_y {
+                from.y + from.h as i32 - 1 - (y - to_y)
+            } else {
+                from.y + (y - to_y)
+            };
+            for x in x0..x1 {
+                let sx = if style.flip_x {
+                    from.x + from.w as i32 - 1 - (x - to_x)
+                } else {
+                    from.x + (x - to_x)
+                };
+                let idx = sy as usize * src_pitch + sx as usize * depth;
+                let px = &src_buf[idx..idx + 4];
+                let col = [px[0] as f64, px[1] as f64, px[2] as f64, px[3] as f64];
+                if col[3] == 0.0 {
+                    continue;
+                }
+                let (x, y) = (x as usize, y as usize);
+                blend_over(
+                    &mut self.framebuffer[y * pitch + x * depth..y * pitch + (x + 1) * depth],
+                    style.shade(col),
+                );
+            }
+        }
+    }
 }
@@ -1,12 +1,33 @@
 use crate::animation::AnimationSM;
+use crate::render::DrawCmd;
+use crate::screen::{BlitStyle, Sampling, Transform};
 use crate::texture::Texture;
-use crate::types::Vec2i;
+use crate::types::{Vec2f, Vec2i};
 use std::rc::Rc;
 
+/// Name of the layer a sprite is created with
+pub const BODY: &str = "body";
+
+/// One animated part of a sprite, e.g. a tank's body, its turret or a damage flash
+pub struct Layer {
+    pub name: String,
+    pub animation_sm: AnimationSM,
+    // Where the layer's frames are pinned, relative to the sprite's position
+    pub offset: Vec2i,
+    // Layers are drawn from lowest to highest order
+    pub order: i32,
+    pub visible: bool,
+    // Radians clockwise about the frame's pivot
+    pub rotation: f64,
+    // Flips here mirror every frame, on top of any flips the frames have themselves
+    pub style: BlitStyle,
+}
+
 pub struct Sprite {
     image: Rc<Texture>,
     // pub animation: Rc<Animation>,
-    pub animation_sm: AnimationSM,
+    // Kept sorted by order, back to front
+    layers: Vec<Layer>,
     pub position: Vec2i,
 }
 
@@ -14,24 +35,137 @@ impl Sprite {
     pub fn new(image: &Rc<Texture>, animation_sm: AnimationSM, position: Vec2i) -> Self {
         Self {
             image: Rc::clone(image),
-            animation_sm: animation_sm,
+            layers: vec![Layer {
+                name: BODY.to_string(),
+                animation_sm,
+                offset: Vec2i(0, 0),
+                order: 0,
+                visible: true,
+                rotation: 0.0,
+                style: BlitStyle::PLAIN,
+            }],
             position,
         }
     }
-}
 
-pub trait DrawSpriteExt {
-    fn draw_sprite(&mut self, s: &mut Sprite, cur_frame: usize);
-}
+    /// Adds a layer drawn from the same image; layers with equal order are drawn in the order added
+    pub fn with_layer(
+        mut self,
+        name: &str,
+        animation_sm: AnimationSM,
+        offset: Vec2i,
+        order: i32,
+    ) -> Self {
+        let at = self
+            .layers
+            .iter()
+            .position(|l| l.order > order)
+            .unwrap_or(self.layers.len());
+        self.layers.insert(
+            at,
+            Layer {
+                name: name.to_string(),
+                animation_sm,
+                offset,
+                order,
+                visible: true,
+                rotation: 0.0,
+                style: BlitStyle::PLAIN,
+            },
+        );
+        self
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    /// Sends input to every layer; layers without a matching transition ignore it
+    pub fn input(&mut self, input: &str, now: usize) {
+        for layer in self.layers.iter_mut() {
+            layer.animation_sm.input(input, now);
+        }
+    }
+
+    pub fn update_anims(&mut self, now: usize) {
+        for layer in self.layers.iter_mut() {
+            layer.animation_sm.update_anim(now);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn pause(&mut self, now: usize) {
+        for layer in self.layers.iter_mut() {
+            layer.animation_sm.pause(now);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn resume(&mut self, now: usize) {
+        for layer in self.layers.iter_mut() {
+            layer.animation_sm.resume(now);
+        }
+    }
+
+    /// Speeds up or slows down every layer; 1.0 is normal speed
+    pub fn set_speed(&mut self, speed: f64, now: usize) {
+        for layer in self.layers.iter_mut() {
+            layer.animation_sm.set_speed(speed, now);
+        }
+    }
 
-use crate::screen::Screen;
-impl<'fb> DrawSpriteExt for Screen<'fb> {
-    fn draw_sprite(&mut self, s: &mut Sprite, cur_frame: usize) {
-        let frame = s
-            .animation_sm
-            .current_anim(cur_frame)
-            .current_frame(cur_frame);
+    /// What to draw for each visible layer at its current frame, back to front
+    pub fn draw_cmds(&mut self, now: usize) -> Vec<DrawCmd> {
+        let mut cmds = vec![];
+        for layer in self.layers.iter_mut().filter(|l| l.visible) {
+            let anim = layer.animation_sm.current_anim(now);
+            let frame = anim.current_frame(now);
+            let pivot = anim.current_pivot(now);
+            let (flip_x, flip_y) = anim.current_flip(now);
+            // Empty frames are how a layer shows nothing
+            if frame.w == 0 || frame.h == 0 {
+                continue;
+            }
+            let style = BlitStyle {
+                flip_x: layer.style.flip_x != flip_x,
+                flip_y: layer.style.flip_y != flip_y,
+                ..layer.style
+            };
+
+            let at = Vec2i(
+                self.position.0 + layer.offset.0,
+                self.position.1 + layer.offset.1,
+            );
+            cmds.push(if layer.rotation == 0.0 {
+                DrawCmd::Blit {
+                    texture: Rc::clone(&self.image),
+                    from: frame,
+                    to: Vec2i(at.0 - pivot.0, at.1 - pivot.1),
+                    style,
+                }
+            } else {
+                DrawCmd::Transformed {
+                    texture: Rc::clone(&self.image),
+                    from: frame,
+                    to: Vec2f(at.0 as f64, at.1 as f64),
+                    xform: Transform {
+                        pivot: Vec2f(pivot.0 as f64, pivot.1 as f64),
+                        rotation: layer.rotation,
+                        scale: Vec2f(1.0, 1.0),
+                    },
+                    sampling: Sampling::Nearest,
+                    style,
+                }
+            });
+        }
+        cmds
+    }
 
-        self.bitblt(&s.image, frame.clone(), s.position);
+    /// Takes the events reported by all layers since the last call
+    pub fn drain_events(&mut self) -> Vec<String> {
+        self.layers
+            .iter_mut()
+            .flat_map(|l| l.animation_sm.drain_events())
+            .collect()
     }
 }
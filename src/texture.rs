@@ -42,7 +42,19 @@ impl Texture {
         &self.image
     }
 
-    #[allow(dead_code)]
+    /// Turns dark marks on a clear background (like the glyphs of a font sheet) into white marks
+    /// of the same coverage, so a tinted blit can draw them in any colour
+    pub fn into_ink(mut self) -> Self {
+        for px in self.image.chunks_exact_mut(self.depth) {
+            // Premultiplied, so alpha minus the (grey) colour is how much ink covers the pixel
+            let ink = px[3].saturating_sub(px[0]);
+            for component in px.iter_mut() {
+                *component = ink;
+            }
+        }
+        self
+    }
+
     pub fn valid_frame(&self, frame: Rect) -> bool {
         0 <= frame.x
             && (frame.x + frame.w as i32) <= (self.width as i32)
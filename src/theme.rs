@@ -0,0 +1,113 @@
+use rand::Rng;
+
+use crate::assets::AssetError;
+use crate::render::{DrawCmd, DrawList, RenderLayer};
+use crate::types::{Rect, Rgba, Vec2i};
+
+/// Decoration drawn over a level to give it some atmosphere
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ambient {
+    Clear,
+    Snowfall,
+    Leaves,
+    Dust,
+}
+
+/// Everything that changes the look of a level without changing how it plays
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    /// Offset from the "snow" panel of mario_tileset.png to this theme's panel
+    pub tile_offset: Vec2i,
+    pub background: Rgba,
+    pub ambient: Ambient,
+}
+
+/*
+mario_tileset.png is laid out as six identical panels of ground tiles, three across and two down,
+each 272x360 pixels. Tile coordinates in get_tile_rect are given for the "snow" panel in the bottom
+right, so each theme just records how far its panel is from that one.
+*/
+pub const THEMES: [Theme; 6] = [
+    Theme {
+        name: "snow",
+        tile_offset: Vec2i(0, 0),
+        background: Rgba(255, 197, 255, 255),
+        ambient: Ambient::Snowfall,
+    },
+    Theme {
+        name: "grass",
+        tile_offset: Vec2i(-544, -360),
+        background: Rgba(150, 210, 255, 255),
+        ambient: Ambient::Clear,
+    },
+    Theme {
+        name: "vanilla",
+        tile_offset: Vec2i(-272, -360),
+        background: Rgba(240, 232, 190, 255),
+        ambient: Ambient::Clear,
+    },
+    Theme {
+        name: "forest",
+        tile_offset: Vec2i(0, -360),
+        background: Rgba(120, 150, 110, 255),
+        ambient: Ambient::Leaves,
+    },
+    Theme {
+        name: "chocolate",
+        tile_offset: Vec2i(-544, 0),
+        background: Rgba(230, 190, 140, 255),
+        ambient: Ambient::Dust,
+    },
+    Theme {
+        name: "cave",
+        tile_offset: Vec2i(-272, 0),
+        background: Rgba(60, 60, 80, 255),
+        ambient: Ambient::Dust,
+    },
+];
+
+impl Theme {
+    pub fn by_name(name: &str) -> Result<Theme, AssetError> {
+        THEMES
+            .iter()
+            .find(|t| t.name == name)
+            .copied()
+            .ok_or_else(|| AssetError::UnknownTheme(name.to_string()))
+    }
+
+    pub fn random(rng: &mut impl Rng) -> Theme {
+        THEMES[rng.gen_range(0..THEMES.len())]
+    }
+
+    /// Draws this theme's ambient effect over the part of the world in view.
+    /// Particles are placed from the frame count alone, so there's no state to carry around.
+    pub fn draw_ambient(&self, list: &mut DrawList, view: Rect, frame_count: usize) {
+        let (count, col, size, fall, sway) = match self.ambient {
+            Ambient::Clear => return,
+            Ambient::Snowfall => (60, Rgba(255, 255, 255, 200), 2, 1.0, 12.0),
+            Ambient::Leaves => (20, Rgba(150, 90, 30, 255), 3, 0.6, 30.0),
+            Ambient::Dust => (40, Rgba(200, 180, 150, 140), 1, 0.2, 6.0),
+        };
+
+        let Rect { x, y, w, h } = view;
+        let t = frame_count as f64;
+        for i in 0..count {
+            // Cheap per-particle pseudo-random numbers
+            let seed = (i as u32).wrapping_mul(2_654_435_761);
+            let x0 = (seed % w as u32) as f64;
+            let y0 = ((seed >> 8) % h as u32) as f64;
+            let speed = fall * (0.5 + (seed >> 24) as f64 / 255.0);
+
+            let px = (x0 + (t / 40.0 + i as f64).sin() * sway).rem_euclid(w as f64);
+            let py = (y0 + t * speed).rem_euclid(h as f64);
+            let r = Rect {
+                x: x + px as i32,
+                y: y + py as i32,
+                w: size,
+                h: size,
+            };
+            list.push(RenderLayer::Weather, 0, DrawCmd::Rect(r, col));
+        }
+    }
+}
@@ -89,3 +89,14 @@ impl Vec2f {
         Vec2f(self.0 * scalar, self.1 * scalar)
     }
 }
+
+/// The same angle as a (in radians), in [0, 2π)
+pub fn normalize_angle(a: f64) -> f64 {
+    let a = a.rem_euclid(std::f64::consts::TAU);
+    // Tiny negative angles round up to exactly 2π
+    if a >= std::f64::consts::TAU {
+        0.0
+    } else {
+        a
+    }
+}
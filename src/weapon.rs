@@ -0,0 +1,194 @@
+use crate::collision::DamageType;
+use crate::render::{DrawCmd, DrawList, RenderLayer};
+use crate::types::{Rgba, Vec2f};
+
+/// How a weapon's shots get where they're going
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Delivery {
+    // Shells that fly straight, glancing off terrain until their bounces run out
+    Shell,
+    // An instant beam, up to range pixels long, that passes through tanks and stops at terrain
+    Beam { range: f64 },
+    // A shell lobbed over everything in its way; it touches nothing until it comes down range
+    // pixels away
+    Lobbed { range: f64 },
+}
+
+// Uncharged shots fly MIN_POWER times as fast as their weapon's speed (or, lobbed, as far as its
+// range), and fully charged ones MAX_POWER times
+const MIN_POWER: f64 = 0.5;
+const MAX_POWER: f64 = 1.5;
+
+// Shots lose their weapon's falloff fraction of their damage every FALLOFF_DISTANCE pixels flown
+const FALLOFF_DISTANCE: f64 = 100.0;
+
+/*
+Everything about a weapon lives in its entry in WEAPONS, so adding or rebalancing one doesn't touch
+the collision code. Weapons with a blast radius don't hurt what they hit directly: they burst when
+they're spent (on hitting a tank, running out of bounces or landing) and hurt everything in range
+(see collision::explode).
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Weapon {
+    pub name: &'static str,
+    pub delivery: Delivery,
+    // Shells fired at once, fanned out evenly over spread radians
+    pub shells: usize,
+    pub spread: f64,
+    // Pixels per frame
+    pub speed: f64,
+    // HP taken off each tank hit, before armor and falloff
+    pub damage: i32,
+    pub kind: DamageType,
+    // Fraction of its damage a shot loses over every FALLOFF_DISTANCE pixels it flies
+    pub falloff: f64,
+    // Times a shell glances off terrain; it's spent on the next terrain it hits
+    pub bounces: i32,
+    // Frames a shell flies before it's spent, if nothing stops it sooner
+    pub lifetime: usize,
+    // HP taken off each destructible terrain block hit
+    pub terrain_damage: i32,
+    // Tanks and destructible terrain within this many pixels of where a shell bursts are hurt; 0
+    // for no burst
+    pub blast_radius: f64,
+    // Size of each shell's collider
+    pub size: (u16, u16),
+}
+
+pub const WEAPONS: [Weapon; 5] = [
+    Weapon {
+        name: "Shell",
+        delivery: Delivery::Shell,
+        shells: 1,
+        spread: 0.0,
+        speed: 2.0,
+        damage: 3,
+        kind: DamageType::Kinetic,
+        falloff: 0.1,
+        bounces: 5,
+        lifetime: 600,
+        terrain_damage: 1,
+        blast_radius: 0.0,
+        size: (14, 7),
+    },
+    Weapon {
+        name: "Heavy shell",
+        delivery: Delivery::Shell,
+        shells: 1,
+        spread: 0.0,
+        speed: 1.5,
+        damage: 5,
+        kind: DamageType::Explosive,
+        falloff: 0.0,
+        bounces: 0,
+        lifetime: 600,
+        terrain_damage: 2,
+        blast_radius: 40.0,
+        size: (18, 10),
+    },
+    Weapon {
+        name: "Spread shot",
+        delivery: Delivery::Shell,
+        shells: 5,
+        spread: 0.6,
+        speed: 2.5,
+        damage: 2,
+        kind: DamageType::Kinetic,
+        falloff: 0.3,
+        bounces: 1,
+        lifetime: 240,
+        terrain_damage: 1,
+        blast_radius: 0.0,
+        size: (10, 5),
+    },
+    Weapon {
+        name: "Laser",
+        delivery: Delivery::Beam { range: 400.0 },
+        shells: 1,
+        spread: 0.0,
+        speed: 0.0,
+        damage: 2,
+        kind: DamageType::Energy,
+        falloff: 0.2,
+        bounces: 0,
+        lifetime: 0,
+        terrain_damage: 1,
+        blast_radius: 0.0,
+        size: (0, 0),
+    },
+    Weapon {
+        name: "Mortar",
+        delivery: Delivery::Lobbed { range: 220.0 },
+        shells: 1,
+        spread: 0.0,
+        speed: 2.0,
+        damage: 4,
+        kind: DamageType::Explosive,
+        falloff: 0.0,
+        bounces: 0,
+        lifetime: 600,
+        terrain_damage: 2,
+        blast_radius: 48.0,
+        size: (12, 12),
+    },
+];
+
+/// How much faster (or further) than normal a shot charged from 0 to 1 flies
+pub fn power(charge: f64) -> f64 {
+    MIN_POWER + (MAX_POWER - MIN_POWER) * charge.clamp(0.0, 1.0)
+}
+
+impl Weapon {
+    /// Damage a shot does after flying distance pixels, before armor; always at least 1
+    pub fn damage_at(&self, distance: f64) -> i32 {
+        let kept = (1.0 - self.falloff).powf(distance / FALLOFF_DISTANCE);
+        ((self.damage as f64 * kept).round() as i32).max(1)
+    }
+
+    /// The direction each shell of a shot aimed at aim (radians) sets off in
+    pub fn shell_angles(&self, aim: f64) -> Vec<f64> {
+        if self.shells <= 1 {
+            return vec![aim];
+        }
+        (0..self.shells)
+            .map(|i| aim + self.spread * (i as f64 / (self.shells - 1) as f64 - 0.5))
+            .collect()
+    }
+}
+
+// Frames a laser beam stays on screen, fading out
+const BEAM_FRAMES: usize = 12;
+const BEAM_COLOUR: Rgba = Rgba(255, 80, 60, 255);
+
+/// What's left on screen of a laser shot
+pub struct Beam {
+    pub from: Vec2f,
+    pub to: Vec2f,
+    pub fired_at: usize,
+}
+
+impl Beam {
+    pub fn is_visible(&self, frame: usize) -> bool {
+        frame - self.fired_at < BEAM_FRAMES
+    }
+
+    /// Submits the beam, in world coordinates
+    pub fn draw(&self, list: &mut DrawList, frame: usize) {
+        let fade = 1.0 - (frame - self.fired_at) as f64 / BEAM_FRAMES as f64;
+        let alpha = (fade.clamp(0.0, 1.0) * 255.0) as u8;
+        list.push(
+            RenderLayer::Effects,
+            0,
+            DrawCmd::Polyline(
+                vec![self.from, self.to],
+                4.0 * fade,
+                Rgba(BEAM_COLOUR.0, BEAM_COLOUR.1, BEAM_COLOUR.2, alpha / 2),
+            ),
+        );
+        list.push(
+            RenderLayer::Effects,
+            1,
+            DrawCmd::Polyline(vec![self.from, self.to], 1.5, Rgba(255, 230, 220, alpha)),
+        );
+    }
+}